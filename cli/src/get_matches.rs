@@ -7,6 +7,9 @@ const SHELL: &str = if cfg!(target_os = "windows") {
 };
 
 pub fn get_matches() -> ArgMatches {
+    // clap needs the default as a string
+    let concurrency = install_npm::DEFAULT_CONCURRENCY.to_string();
+
     Command::new("vortex")
         .version(crate_version!())
         .propagate_version(true)
@@ -35,7 +38,89 @@ pub fn get_matches() -> ArgMatches {
         .subcommand(
             Command::new("install")
                 .about("Install a package.")
+                .arg(
+                    Arg::new("packages")
+                        .help("Packages to install instead of the package.json dependencies")
+                        .multiple_values(true),
+                )
+                .arg(
+                    Arg::new("network-concurrency")
+                        .long("network-concurrency")
+                        .help("Maximum number of registry requests in flight at once")
+                        .default_value(&concurrency),
+                )
+                .arg(
+                    Arg::new("frozen-lockfile")
+                        .long("frozen-lockfile")
+                        .help("Fail instead of updating vortex-lock.json"),
+                )
+                .arg(
+                    Arg::new("offline")
+                        .long("offline")
+                        .conflicts_with("prefer-offline")
+                        .help("Only install from the local cache, never the network"),
+                )
+                .arg(
+                    Arg::new("prefer-offline")
+                        .long("prefer-offline")
+                        .help("Use cached data when there is some, without revalidating it"),
+                )
+                .arg(
+                    Arg::new("no-package-lock")
+                        .long("no-package-lock")
+                        .help("Ignore package-lock.json instead of keeping it up to date"),
+                )
+                .arg(
+                    Arg::new("os")
+                        .long("os")
+                        .takes_value(true)
+                        .help("Install optional dependencies for another OS (e.g. darwin)"),
+                )
+                .arg(
+                    Arg::new("cpu")
+                        .long("cpu")
+                        .takes_value(true)
+                        .help("Install optional dependencies for another CPU (e.g. arm64)"),
+                )
+                .arg(
+                    Arg::new("libc")
+                        .long("libc")
+                        .takes_value(true)
+                        .help("Install optional dependencies for another libc (glibc or musl)"),
+                )
+                .arg(
+                    Arg::new("strict-peer-deps")
+                        .long("strict-peer-deps")
+                        .help("Fail instead of warning when a peer dependency isn't satisfied"),
+                )
+                .arg(
+                    Arg::new("omit")
+                        .long("omit")
+                        .takes_value(true)
+                        .multiple_occurrences(true)
+                        .possible_values(["dev", "optional", "peer"])
+                        .help("Don't install this kind of dependency (dev if NODE_ENV=production)"),
+                )
                 .aliases(&["i", "add"]),
         )
+        .subcommand(
+            Command::new("import")
+                .about("Create vortex-lock.json from a yarn.lock or pnpm-lock.yaml.")
+                .arg(
+                    Arg::new("lockfile")
+                        .help("The lockfile to import, instead of looking for one"),
+                ),
+        )
+        .subcommand(
+            Command::new("ls")
+                .about("List the installed packages as a tree.")
+                .arg(
+                    Arg::new("depth")
+                        .long("depth")
+                        .takes_value(true)
+                        .help("How many levels of dependencies to show (0 for direct ones only)"),
+                )
+                .aliases(&["list"]),
+        )
         .get_matches()
 }
@@ -1,8 +1,9 @@
+mod manifest;
 mod panic_hook;
 pub mod run_script;
 
 mod subcommands;
-use crate::subcommands::{init::init, install::install, run::run};
+use crate::subcommands::{import::import, init::init, install::install, ls::ls, run::run};
 
 mod get_matches;
 use crate::get_matches::get_matches;
@@ -18,6 +19,8 @@ async fn main() -> Result<(), ()> {
         Some(("run-script", matches)) => run(matches),
         Some(("test", matches)) => run_script::alias("test", matches),
         Some(("install", matches)) => install(matches).await,
+        Some(("import", matches)) => import(matches),
+        Some(("ls", matches)) => ls(matches),
         _ => unreachable!(
             "Command is not defined in the command list, but subcommand_required is enabled"
         ),
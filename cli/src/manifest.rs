@@ -0,0 +1,39 @@
+use owo_colors::colored::*;
+use serde::de::DeserializeOwned;
+use std::fs;
+use std::path::Path;
+
+use crate::run_script::invalid_data;
+
+/// Reads the package.json in the current directory, exiting
+/// with a helpful error if it is missing or invalid
+pub fn read<T: DeserializeOwned>() -> T {
+    let path = Path::new("package.json");
+    if !path.exists() {
+        eprintln!(
+            "{} package.json does not exist. Run 'vortex init' to create one.",
+            "error:".red().bold()
+        );
+        std::process::exit(exitcode::NOINPUT);
+    }
+
+    let contents = match fs::read_to_string(path) {
+        Ok(c) => c,
+        Err(e) => {
+            eprintln!(
+                "{} failed to read package.json: {}",
+                "error:".red().bold(),
+                e
+            );
+            std::process::exit(exitcode::IOERR)
+        }
+    };
+
+    match serde_json::from_str(contents.as_str()) {
+        Ok(json) => json,
+        _ => {
+            invalid_data("package.json is invalid");
+            unreachable!();
+        }
+    }
+}
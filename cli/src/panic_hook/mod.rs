@@ -1,6 +1,6 @@
 use owo_colors::OwoColorize;
 use std::{
-    panic::{self, PanicInfo},
+    panic::{self, PanicHookInfo},
     path::PathBuf,
 };
 pub mod report;
@@ -31,7 +31,7 @@ It's located at {}.
 }
 
 /// Utility function which will handle dumping information to disk
-pub fn handle_dump(version: &str, panic_info: &PanicInfo) -> Option<PathBuf> {
+pub fn handle_dump(version: &str, panic_info: &PanicHookInfo) -> Option<PathBuf> {
     let mut expl = String::new();
 
     #[cfg(feature = "nightly")]
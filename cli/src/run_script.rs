@@ -1,8 +1,10 @@
 use clap::ArgMatches;
 use owo_colors::colored::*;
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::path::Path;
-use std::{collections::HashMap, fs};
+
+use crate::manifest;
 
 pub fn invalid_data(err: &str) {
     eprintln!("{} {}", "error:".red().bold(), err);
@@ -48,35 +50,7 @@ pub fn run_script(name: &str, scripts: HashMap<String, String>, shell: &str) {
 }
 
 pub fn get_scripts() -> Option<HashMap<String, String>> {
-    let path = Path::new("package.json");
-    if !path.exists() {
-        eprintln!(
-            "{} package.json does not exist. Run 'vortex init' to create one.",
-            "error:".red().bold()
-        );
-        std::process::exit(exitcode::NOINPUT);
-    }
-
-    let contents = match fs::read_to_string("package.json") {
-        Ok(c) => c,
-        Err(e) => {
-            eprintln!(
-                "{} failed to read package.json: {}",
-                "error:".red().bold(),
-                e
-            );
-            std::process::exit(exitcode::IOERR)
-        }
-    };
-
-    let parsed: Contents = match serde_json::from_str(contents.as_str()) {
-        Ok(json) => json,
-        _ => {
-            invalid_data("package.json is invalid");
-            unreachable!();
-        }
-    };
-
+    let parsed: Contents = manifest::read();
     parsed.scripts
 }
 
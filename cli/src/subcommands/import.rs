@@ -0,0 +1,88 @@
+use clap::ArgMatches;
+use install_npm::{
+    import_pnpm_lock, import_yarn_lock, Lockfile, Manifest, LOCKFILE_NAME, PNPM_LOCK_NAME,
+    YARN_LOCK_NAME,
+};
+use owo_colors::OwoColorize;
+use std::fs;
+use std::path::Path;
+
+use super::install::{fail, npmrc};
+use crate::manifest;
+
+/// Converts another package manager's lockfile into vortex-lock.json, so
+/// switching to vortex installs the same versions
+pub fn import(matches: &ArgMatches) {
+    let manifest = manifest::read::<Manifest>();
+
+    let path = match matches.value_of("lockfile") {
+        Some(path) => Path::new(path),
+        None => match [YARN_LOCK_NAME, PNPM_LOCK_NAME]
+            .iter()
+            .map(Path::new)
+            .find(|path| path.exists())
+        {
+            Some(path) => path,
+            None => fail(
+                &format!(
+                    "there is no {} or {} to import",
+                    YARN_LOCK_NAME, PNPM_LOCK_NAME
+                ),
+                exitcode::NOINPUT,
+            ),
+        },
+    };
+    let contents = match fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(e) => fail(
+            &format!("failed to read {}: {}", path.display(), e),
+            exitcode::NOINPUT,
+        ),
+    };
+
+    let dependencies = manifest.all_dependencies();
+    let is_pnpm = matches!(
+        path.extension().and_then(|extension| extension.to_str()),
+        Some("yaml" | "yml")
+    );
+    let imported = if is_pnpm {
+        import_pnpm_lock(&contents, &dependencies, &npmrc())
+    } else {
+        import_yarn_lock(&contents, &dependencies)
+    };
+    let imported = match imported {
+        Ok(imported) => imported,
+        Err(e) => fail(
+            &format!("failed to import {}: {}", path.display(), e),
+            exitcode::DATAERR,
+        ),
+    };
+
+    if let Err(e) = Lockfile::from_graph(&imported.graph).write(Path::new(LOCKFILE_NAME)) {
+        fail(
+            &format!("failed to write {}: {}", LOCKFILE_NAME, e),
+            exitcode::IOERR,
+        );
+    }
+
+    for unmapped in &imported.unmapped {
+        eprintln!(
+            "{} could not import {}",
+            "warning:".yellow().bold(),
+            unmapped
+        );
+    }
+    if !imported.dropped.is_empty() {
+        eprintln!(
+            "{} {} packages depending on them will be resolved again on the next install",
+            "warning:".yellow().bold(),
+            imported.dropped.len()
+        );
+    }
+    println!(
+        "📦 Imported {} packages from {} into {}",
+        imported.graph.len(),
+        path.display(),
+        LOCKFILE_NAME
+    );
+}
@@ -64,13 +64,12 @@ pub fn init(matches: &ArgMatches) {
     let dir_name: &str = dir_name_cow.borrow();
     let mut file = File::create("package.json").unwrap();
 
-    match matches.is_present("yes") {
-        true => write_defaults(dir_name, &mut file),
-        false => {}
+    if matches.is_present("yes") {
+        write_defaults(dir_name, &mut file)
     }
 
     let name = Text::new("Package name:")
-        .with_default(dir_name.borrow())
+        .with_default(dir_name)
         .with_validator(&|input| match validate(input) {
             Ok(_) => Ok(()),
             Err(error) => Err(error.to_string()),
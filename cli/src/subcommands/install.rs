@@ -1,24 +1,348 @@
 use clap::ArgMatches;
-use install_npm::walk_dependencies;
-use node_semver::Range;
+use install_npm::{
+    hoist, install_package, peer_conflicts, Cache, Dependency, DependencyKind, Host, Lockfile,
+    Manifest, NetworkMode, Npmrc, Overrides, PackageLock, Resolver, Store, LOCKFILE_NAME,
+    PACKAGE_LOCK_NAME,
+};
 use owo_colors::OwoColorize;
+use std::path::Path;
+
+use crate::manifest;
+
+/// Splits a `name@range` or `name@tag` argument, keeping the leading `@` of
+/// scoped packages. A bare name installs whatever is tagged `latest`
+fn parse_package_arg(arg: &str) -> (String, String) {
+    // Skips the first character, whatever its length, so `@scope/a` keeps
+    // its `@`
+    let start = arg.chars().next().map_or(0, char::len_utf8);
+    match arg[start..].find('@') {
+        Some(index) => (
+            String::from(&arg[..start + index]),
+            String::from(&arg[start + index + 1..]),
+        ),
+        None => (String::from(arg), String::from("latest")),
+    }
+}
+
+/// Prints an error and exits
+pub(crate) fn fail(message: &str, code: i32) -> ! {
+    eprintln!("{} {}", "error:".red().bold(), message);
+    std::process::exit(code);
+}
+
+/// Reads the registry settings of the project in the current directory,
+/// layered over the user's and the global ones
+pub(crate) fn npmrc() -> Npmrc {
+    let home = home::home_dir();
+    match Npmrc::load(Path::new("."), home.as_deref()) {
+        Ok(npmrc) => npmrc,
+        Err(e) => fail(&e.to_string(), exitcode::CONFIG),
+    }
+}
+
+/// The platform to install for: this one, unless `--os`, `--cpu` or
+/// `--libc` say otherwise
+fn host(matches: &ArgMatches) -> Host {
+    let mut host = Host::current();
+    if let Some(os) = matches.value_of("os") {
+        host.os = os.to_string();
+    }
+    if let Some(cpu) = matches.value_of("cpu") {
+        host.cpu = cpu.to_string();
+    }
+    if let Some(libc) = matches.value_of("libc") {
+        host.libc = Some(libc.to_string());
+    }
+    host
+}
+
+/// The kinds of dependencies not to install: the ones passed with `--omit`,
+/// or devDependencies when `NODE_ENV=production`, like npm
+fn omitted(matches: &ArgMatches) -> Vec<DependencyKind> {
+    match matches.values_of("omit") {
+        Some(kinds) => kinds
+            .map(|kind| match kind {
+                "dev" => DependencyKind::Dev,
+                "optional" => DependencyKind::Optional,
+                _ => DependencyKind::Peer,
+            })
+            .collect(),
+        None if std::env::var("NODE_ENV").as_deref() == Ok("production") => {
+            vec![DependencyKind::Dev]
+        }
+        None => Vec::new(),
+    }
+}
+
+pub async fn install(matches: &ArgMatches) {
+    // Only installs of the whole manifest are locked, as packages named on
+    // the command line aren't saved to package.json
+    let (wanted, manifest): (Vec<Dependency>, Option<Manifest>) =
+        match matches.values_of("packages") {
+            Some(packages) => (
+                packages
+                    .map(|package| {
+                        let (name, range) = parse_package_arg(package);
+                        Dependency::new(&name, &range, DependencyKind::Prod)
+                    })
+                    .collect(),
+                None,
+            ),
+            None => {
+                let manifest = manifest::read::<Manifest>();
+                (manifest.all_dependencies(), Some(manifest))
+            }
+        };
+    let locking = manifest.is_some();
+    let overrides = match manifest.as_ref().map(Overrides::from_manifest) {
+        Some(Ok(overrides)) => overrides,
+        Some(Err(e)) => fail(&e.to_string(), exitcode::DATAERR),
+        None => Overrides::default(),
+    };
+
+    let lockfile_path = Path::new(LOCKFILE_NAME);
+    let lockfile = if locking {
+        match Lockfile::read(lockfile_path) {
+            Ok(lockfile) => lockfile,
+            Err(e) => fail(
+                &format!("failed to read {}: {}", LOCKFILE_NAME, e),
+                exitcode::DATAERR,
+            ),
+        }
+    } else {
+        None
+    };
+
+    // Kept up to date alongside vortex-lock.json when the project already
+    // has one, so npm and vortex can be used side by side
+    let package_lock_path = Path::new(PACKAGE_LOCK_NAME);
+    let mut package_lock = if locking && !matches.is_present("no-package-lock") {
+        match PackageLock::read(package_lock_path) {
+            Ok(package_lock) => package_lock,
+            Err(e) => fail(
+                &format!("failed to read {}: {}", PACKAGE_LOCK_NAME, e),
+                exitcode::DATAERR,
+            ),
+        }
+    } else {
+        None
+    };
+
+    if matches.is_present("frozen-lockfile") {
+        match &lockfile {
+            Some(lockfile) if lockfile.matches(&wanted, &overrides) => {}
+            Some(_) => fail(
+                &format!(
+                    "package.json and {} are out of sync, and --frozen-lockfile was passed",
+                    LOCKFILE_NAME
+                ),
+                exitcode::DATAERR,
+            ),
+            None => fail(
+                &format!(
+                    "{} is missing, and --frozen-lockfile was passed",
+                    LOCKFILE_NAME
+                ),
+                exitcode::NOINPUT,
+            ),
+        }
+    }
+
+    // Packuments and package files are shared by every project
+    let vortex_dir = home::home_dir().map(|home| home.join(".vortex"));
+    let mode = if matches.is_present("offline") {
+        NetworkMode::Offline
+    } else if matches.is_present("prefer-offline") {
+        NetworkMode::PreferOffline
+    } else {
+        NetworkMode::Online
+    };
+    if mode == NetworkMode::Offline && vortex_dir.is_none() {
+        fail(
+            "--offline was passed, but there is no home directory to find the cache in",
+            exitcode::CONFIG,
+        );
+    }
+
+    let npmrc = npmrc();
+    let cache = vortex_dir
+        .as_ref()
+        .map(|vortex_dir| Cache::new(vortex_dir.join("cache")));
+    let store = vortex_dir.as_ref().map(|vortex_dir| {
+        let store = Store::new(vortex_dir.join("store"))
+            .with_network_mode(mode)
+            .with_npmrc(npmrc.clone());
+        match &cache {
+            Some(cache) => store.with_cache(cache.clone()),
+            None => store,
+        }
+    });
+
+    let concurrency = match matches.value_of_t("network-concurrency") {
+        Ok(concurrency) => concurrency,
+        Err(e) => e.exit(),
+    };
+    let mut resolver = Resolver::new(npmrc.registry())
+        .with_npmrc(npmrc.clone())
+        .with_concurrency(concurrency)
+        .with_network_mode(mode);
+    if let Some(cache) = &cache {
+        resolver = resolver.with_cache(cache.clone());
+    }
+    if let Some(store) = &store {
+        resolver = resolver.with_store(store.clone());
+    }
 
-pub async fn install(_matches: &ArgMatches) {
     println!("📦 Installing dependencies...");
-    let mut dependencies = walk_dependencies(
-        &String::from("express"),
-        Range::parse("4.18.1").unwrap(),
-        "https://registry.npmjs.org",
-    )
-    .await
-    .unwrap();
-    dependencies.dedup();
-
-    for dependency in dependencies.iter() {
-        if dependency.dependencies.is_some() {
-            for (key, value) in dependency.dependencies.as_ref().unwrap() {
-                println!("{} {} {}", "+".green(), key, value);
+    let (graph, locked) = match lockfile {
+        Some(lockfile) if lockfile.matches(&wanted, &overrides) => {
+            (lockfile.to_graph(), Some(lockfile))
+        }
+        lockfile => {
+            let (mut locked, mut locked_git) = match &lockfile {
+                Some(lockfile) => (lockfile.locked_packages(), lockfile.locked_git()),
+                None => Default::default(),
+            };
+            if let Some(package_lock) = &package_lock {
+                locked.extend(package_lock.locked_packages());
+                locked_git.extend(package_lock.locked_git());
             }
+            resolver = resolver
+                .with_locked(locked)
+                .with_locked_git(locked_git)
+                .with_overrides(overrides.clone());
+            (resolver.resolve(&wanted).await, None)
+        }
+    };
+    let graph = match graph {
+        Ok(graph) => graph,
+        Err(e) => fail(
+            &format!("failed to resolve dependencies: {}", e),
+            exitcode::UNAVAILABLE,
+        ),
+    };
+
+    let conflicts = peer_conflicts(&graph);
+    for conflict in &conflicts {
+        eprintln!("{} {}", "warning:".yellow().bold(), conflict);
+    }
+    if !conflicts.is_empty() && matches.is_present("strict-peer-deps") {
+        fail(
+            "peer dependencies aren't satisfied, and --strict-peer-deps was passed",
+            exitcode::DATAERR,
+        );
+    }
+
+    // Like npm, the lockfile keeps the optional packages of every platform
+    // and every omitted package, but only the ones needed here are
+    // installed. Omitted packages are pruned by their locked flags
+    let omitted = omitted(matches);
+    let pruned = locked
+        .unwrap_or_else(|| Lockfile::from_graph(&graph))
+        .omitted(&omitted);
+    let supported = graph.supported_by(&host(matches));
+    let required = graph.reachable(&[DependencyKind::Optional]);
+
+    let layout = hoist(&graph);
+    for (path, id) in &layout {
+        if pruned.contains(id) || !supported.contains(id) {
+            continue;
+        }
+        let metadata = &graph.node(id).unwrap().metadata;
+        // Packages locked to a git commit are installed from the cache,
+        // which may not have them yet. Without a home directory there is
+        // no cache, and installing them fails
+        let installed = match &store {
+            Some(store) => match resolver.fetch_git(metadata).await {
+                Ok(()) => store.install(metadata, path).await,
+                Err(e) => Err(e),
+            },
+            None => install_package(metadata, &npmrc, path).await,
+        };
+        match installed {
+            Ok(()) => {}
+            Err(e) if !required.contains(id) => eprintln!(
+                "{} skipped optional dependency {}: {}",
+                "warning:".yellow().bold(),
+                id,
+                e
+            ),
+            Err(e) => fail(&format!("failed to install {}: {}", id, e), exitcode::IOERR),
+        }
+    }
+
+    if locking {
+        let lockfile = Lockfile::from_graph(&graph).with_overrides(&overrides);
+        if let Err(e) = lockfile.write(lockfile_path) {
+            fail(
+                &format!("failed to write {}: {}", LOCKFILE_NAME, e),
+                exitcode::IOERR,
+            );
         }
     }
+    if let (Some(package_lock), Some(manifest)) = (&mut package_lock, &manifest) {
+        package_lock.update(manifest, &graph, &layout);
+        if let Err(e) = package_lock.write(package_lock_path) {
+            fail(
+                &format!("failed to write {}: {}", PACKAGE_LOCK_NAME, e),
+                exitcode::IOERR,
+            );
+        }
+    }
+
+    for edge in graph.roots() {
+        if omitted.contains(&edge.kind) {
+            continue;
+        }
+        println!("{} {}", "+".green(), edge.to);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn package_arg_with_range() {
+        assert_eq!(
+            parse_package_arg("express@^4.18.1"),
+            (String::from("express"), String::from("^4.18.1"))
+        );
+    }
+
+    #[test]
+    fn package_arg_with_tag() {
+        assert_eq!(
+            parse_package_arg("typescript@next"),
+            (String::from("typescript"), String::from("next"))
+        );
+    }
+
+    #[test]
+    fn scoped_package_arg() {
+        assert_eq!(
+            parse_package_arg("@types/node@18"),
+            (String::from("@types/node"), String::from("18"))
+        );
+        assert_eq!(
+            parse_package_arg("@types/node"),
+            (String::from("@types/node"), String::from("latest"))
+        );
+    }
+
+    #[test]
+    fn short_and_non_ascii_package_args() {
+        assert_eq!(
+            parse_package_arg(""),
+            (String::new(), String::from("latest"))
+        );
+        assert_eq!(
+            parse_package_arg("é"),
+            (String::from("é"), String::from("latest"))
+        );
+        assert_eq!(
+            parse_package_arg("é@1"),
+            (String::from("é"), String::from("1"))
+        );
+    }
 }
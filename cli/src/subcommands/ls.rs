@@ -0,0 +1,219 @@
+use clap::ArgMatches;
+use install_npm::{Edge, Graph, Lockfile, Manifest, PackageId, Spec, LOCKFILE_NAME};
+use std::collections::HashSet;
+use std::path::Path;
+
+use super::install::fail;
+use crate::manifest;
+
+/// Prints the project's dependency tree, as locked in vortex-lock.json
+pub fn ls(matches: &ArgMatches) {
+    let manifest = manifest::read::<Manifest>();
+
+    let lockfile = match Lockfile::read(Path::new(LOCKFILE_NAME)) {
+        Ok(Some(lockfile)) => lockfile,
+        Ok(None) => fail(
+            &format!(
+                "{} is missing, run 'vortex install' to create it",
+                LOCKFILE_NAME
+            ),
+            exitcode::NOINPUT,
+        ),
+        Err(e) => fail(
+            &format!("failed to read {}: {}", LOCKFILE_NAME, e),
+            exitcode::DATAERR,
+        ),
+    };
+    let graph = match lockfile.to_graph() {
+        Ok(graph) => graph,
+        Err(e) => fail(
+            &format!("failed to read {}: {}", LOCKFILE_NAME, e),
+            exitcode::DATAERR,
+        ),
+    };
+    let depth = match matches.value_of("depth") {
+        Some(_) => match matches.value_of_t("depth") {
+            Ok(depth) => depth,
+            Err(e) => e.exit(),
+        },
+        None => usize::MAX,
+    };
+
+    match (manifest.name, manifest.version) {
+        (Some(name), Some(version)) => println!("{}@{}", name, version),
+        (Some(name), None) => println!("{}", name),
+        _ => println!("."),
+    }
+    for line in tree(&graph, depth) {
+        println!("{}", line);
+    }
+}
+
+/// What an edge is shown as: the package it resolved to, along with the
+/// name it's installed under when it's an alias. Git, tarball and local
+/// packages installed under another name are shown by that name, with
+/// where they come from
+fn label(edge: &Edge) -> String {
+    if edge.name == edge.to.name {
+        return edge.to.to_string();
+    }
+    let spec = edge.overridden.as_ref().unwrap_or(&edge.range);
+    match Spec::parse(&edge.name, spec) {
+        Ok(Spec::Alias { .. }) => format!("{}@npm:{}", edge.name, edge.to),
+        _ => format!("{}@{} ({})", edge.name, edge.to.version, spec),
+    }
+}
+
+/// The lines of the tree below the project, drawn like `npm ls` does.
+/// Packages are only expanded the first time they appear, and edges
+/// resolved by an override are marked as overridden
+fn tree(graph: &Graph, depth: usize) -> Vec<String> {
+    let mut lines = Vec::new();
+    draw(
+        graph,
+        graph.roots(),
+        "",
+        depth,
+        &mut HashSet::new(),
+        &mut lines,
+    );
+    lines
+}
+
+fn draw<'a>(
+    graph: &'a Graph,
+    edges: &'a [Edge],
+    prefix: &str,
+    depth: usize,
+    expanded: &mut HashSet<&'a PackageId>,
+    lines: &mut Vec<String>,
+) {
+    for (index, edge) in edges.iter().enumerate() {
+        let last = index == edges.len() - 1;
+        let children = graph
+            .node(&edge.to)
+            .map(|node| node.edges.as_slice())
+            .unwrap_or_default();
+        let expand = depth > 0 && !children.is_empty() && expanded.insert(&edge.to);
+
+        let mut line = format!(
+            "{}{}{}",
+            prefix,
+            if last { "└── " } else { "├── " },
+            label(edge)
+        );
+        if depth > 0 && !children.is_empty() && !expand {
+            line.push_str(" deduped");
+        }
+        if edge.overridden.is_some() {
+            line.push_str(" overridden");
+        }
+        lines.push(line);
+
+        if expand {
+            let prefix = format!("{}{}", prefix, if last { "    " } else { "│   " });
+            draw(graph, children, &prefix, depth - 1, expanded, lines);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn graph() -> Graph {
+        let lockfile: Lockfile = serde_json::from_str(
+            r#"{
+                "lockfileVersion": 1,
+                "dependencies": {
+                    "a": { "range": "^1.0.0", "version": "1.0.0" },
+                    "c": { "range": "^1.0.0", "version": "1.0.0" }
+                },
+                "packages": {
+                    "a@1.0.0": {
+                        "name": "a",
+                        "version": "1.0.0",
+                        "resolved": "",
+                        "dependencies": {
+                            "b": { "range": "^2.0.0", "version": "2.0.1", "overridden": "2.0.1" }
+                        }
+                    },
+                    "b@2.0.1": {
+                        "name": "b",
+                        "version": "2.0.1",
+                        "resolved": "",
+                        "dependencies": {
+                            "a": { "range": "^1.0.0", "version": "1.0.0" }
+                        }
+                    },
+                    "c@1.0.0": {
+                        "name": "c",
+                        "version": "1.0.0",
+                        "resolved": "",
+                        "dependencies": {
+                            "b": { "range": "^2.0.0", "version": "2.0.1", "overridden": "2.0.1" }
+                        }
+                    }
+                }
+            }"#,
+        )
+        .unwrap();
+        lockfile.to_graph().unwrap()
+    }
+
+    #[test]
+    fn marks_overridden_and_deduped_packages() {
+        assert_eq!(
+            tree(&graph(), usize::MAX),
+            vec![
+                "├── a@1.0.0",
+                "│   └── b@2.0.1 overridden",
+                "│       └── a@1.0.0 deduped",
+                "└── c@1.0.0",
+                "    └── b@2.0.1 deduped overridden",
+            ]
+        );
+    }
+
+    #[test]
+    fn labels_only_npm_aliases_as_aliases() {
+        let lockfile: Lockfile = serde_json::from_str(
+            r#"{
+                "lockfileVersion": 1,
+                "dependencies": {
+                    "a": { "range": "npm:b@^1.0.0", "version": "1.0.0", "name": "b" },
+                    "c": {
+                        "range": "git+https://example.com/b.git#v2.0.0",
+                        "version": "2.0.0",
+                        "name": "b"
+                    },
+                    "d": {
+                        "range": "https://example.com/b-3.0.0.tgz",
+                        "version": "3.0.0",
+                        "name": "b"
+                    }
+                },
+                "packages": {
+                    "b@1.0.0": { "name": "b", "version": "1.0.0", "resolved": "" },
+                    "b@2.0.0": { "name": "b", "version": "2.0.0", "resolved": "" },
+                    "b@3.0.0": { "name": "b", "version": "3.0.0", "resolved": "" }
+                }
+            }"#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            tree(&lockfile.to_graph().unwrap(), usize::MAX),
+            vec![
+                "├── a@npm:b@1.0.0",
+                "├── c@2.0.0 (git+https://example.com/b.git#v2.0.0)",
+                "└── d@3.0.0 (https://example.com/b-3.0.0.tgz)",
+            ]
+        );
+    }
+
+    #[test]
+    fn stops_at_depth() {
+        assert_eq!(tree(&graph(), 0), vec!["├── a@1.0.0", "└── c@1.0.0"]);
+    }
+}
@@ -1,3 +1,5 @@
+pub mod import;
 pub mod init;
 pub mod run;
-pub mod install;
\ No newline at end of file
+pub mod install;
+pub mod ls;
\ No newline at end of file
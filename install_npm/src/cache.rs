@@ -0,0 +1,195 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+use tempdir::TempDir;
+
+use crate::Error;
+
+/// Which packument document to ask the registry for
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, Default)]
+pub(crate) enum PackumentFormat {
+    /// The abbreviated ("corgi") document, which only has the fields
+    /// needed to install a package and is a fraction of the size.
+    /// Registries without it send the full one
+    #[default]
+    Abbreviated,
+    /// The full document, with fields like `time` and `readme`
+    Full,
+}
+
+impl PackumentFormat {
+    pub(crate) fn accept(&self) -> &'static str {
+        match self {
+            PackumentFormat::Abbreviated => {
+                "application/vnd.npm.install-v1+json; q=1.0, application/json; q=0.8, */*"
+            }
+            PackumentFormat::Full => "application/json",
+        }
+    }
+
+    fn dir_name(&self) -> &'static str {
+        match self {
+            PackumentFormat::Abbreviated => "abbreviated",
+            PackumentFormat::Full => "full",
+        }
+    }
+}
+
+/// When the network may be used to fetch packuments and tarballs
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, Default)]
+pub enum NetworkMode {
+    /// Cached packuments are revalidated with the registry
+    #[default]
+    Online,
+    /// Cached data is used as is, and only what's missing is fetched
+    PreferOffline,
+    /// Only cached data is used, and anything missing is an error
+    Offline,
+}
+
+/// The validators a packument was sent with, used to revalidate it
+#[derive(Serialize, Deserialize, PartialEq, Eq, Debug, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct Validators {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub etag: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_modified: Option<String>,
+}
+
+/// A packument as the registry sent it
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub(crate) struct CachedPackument {
+    pub validators: Validators,
+    pub body: String,
+}
+
+/// An on-disk cache of packuments (usually `~/.vortex/cache`). Cached
+/// packuments are revalidated with `If-None-Match`/`If-Modified-Since`, so
+/// a registry that hasn't published anything since only answers with a 304.
+///
+/// Git dependencies are cached too, packed, by commit
+#[derive(Clone, Debug)]
+pub struct Cache {
+    root: PathBuf,
+}
+
+impl Cache {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self { root: root.into() }
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// `packuments/abbreviated/https%3a%2f%2fregistry.npmjs.org/@scope%2fname.json`,
+    /// as registry URLs and scoped names contain slashes. Packuments are
+    /// kept per registry, so moving a scope to another one doesn't serve
+    /// the old registry's packages
+    fn path(&self, registry: &str, name: &str, format: PackumentFormat) -> PathBuf {
+        let escape = |part: &str| part.replace(':', "%3a").replace('/', "%2f");
+        self.root
+            .join("packuments")
+            .join(format.dir_name())
+            .join(escape(registry.trim_end_matches('/')))
+            .join(format!("{}.json", escape(name)))
+    }
+
+    /// Entries are stored as the validators on the first line, followed by
+    /// the packument exactly as it was received
+    pub(crate) fn read(
+        &self,
+        registry: &str,
+        name: &str,
+        format: PackumentFormat,
+    ) -> Option<CachedPackument> {
+        let contents = fs::read_to_string(self.path(registry, name, format)).ok()?;
+        let (validators, body) = contents.split_once('\n')?;
+
+        Some(CachedPackument {
+            validators: serde_json::from_str(validators).ok()?,
+            body: body.to_string(),
+        })
+    }
+
+    pub(crate) fn write(
+        &self,
+        registry: &str,
+        name: &str,
+        format: PackumentFormat,
+        packument: &CachedPackument,
+    ) -> Result<(), Error> {
+        let contents = format!(
+            "{}\n{}",
+            serde_json::to_string(&packument.validators).unwrap(),
+            packument.body
+        );
+        write(&self.path(registry, name, format), contents.as_bytes())
+    }
+
+    /// `git/<sha>.tgz`
+    fn git_path(&self, sha: &str) -> PathBuf {
+        self.root.join("git").join(format!("{sha}.tgz"))
+    }
+
+    /// The tarball a git dependency was packed into at commit `sha`
+    pub(crate) fn read_git(&self, sha: &str) -> Option<Vec<u8>> {
+        fs::read(self.git_path(sha)).ok()
+    }
+
+    pub(crate) fn write_git(&self, sha: &str, tarball: &[u8]) -> Result<(), Error> {
+        write(&self.git_path(sha), tarball)
+    }
+}
+
+/// Writes `contents` next to `path` and renames it over `path`, so
+/// concurrent installs never read half an entry
+fn write(path: &Path, contents: &[u8]) -> Result<(), Error> {
+    let parent = path.parent().ok_or(Error::IoError)?;
+    fs::create_dir_all(parent).map_err(|_| Error::IoError)?;
+
+    let staging = TempDir::new_in(parent, ".vortex-staging").map_err(|_| Error::IoError)?;
+    let staged = staging.path().join("entry");
+    fs::write(&staged, contents).map_err(|_| Error::IoError)?;
+    fs::rename(&staged, path).map_err(|_| Error::IoError)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trips_packuments() {
+        let dir = TempDir::new("vortex-cache").unwrap();
+        let cache = Cache::new(dir.path());
+        let packument = CachedPackument {
+            validators: Validators {
+                etag: Some(String::from("\"abc\"")),
+                last_modified: None,
+            },
+            body: String::from("{\n  \"versions\": {}\n}"),
+        };
+
+        let registry = "https://registry.npmjs.org/";
+        let abbreviated = PackumentFormat::Abbreviated;
+
+        assert_eq!(cache.read(registry, "@scope/a", abbreviated), None);
+        cache
+            .write(registry, "@scope/a", abbreviated, &packument)
+            .unwrap();
+        assert_eq!(
+            cache.read(registry, "@scope/a", abbreviated),
+            Some(packument)
+        );
+        assert_eq!(
+            cache.read(registry, "@scope/a", PackumentFormat::Full),
+            None
+        );
+        assert_eq!(
+            cache.read("https://npm.example.com/", "@scope/a", abbreviated),
+            None
+        );
+    }
+}
@@ -0,0 +1,299 @@
+use std::fs;
+use std::io::Read;
+use std::path::{Component, Path, PathBuf};
+
+use flate2::read::GzDecoder;
+use reqwest::header::AUTHORIZATION;
+use tar::{Archive, EntryType};
+use tempdir::TempDir;
+use tokio::sync::Semaphore;
+
+use crate::local::{self, Local};
+use crate::retry::{self, RetryPolicy};
+use crate::{git, pack, verify, Error, Metadata, Npmrc, CLIENT};
+
+/// Where a package called `name` lives inside `node_modules`,
+/// so `@scope/name` ends up in `node_modules/@scope/name`
+pub fn package_dir(node_modules: &Path, name: &str) -> PathBuf {
+    name.split('/')
+        .fold(node_modules.to_path_buf(), |path, part| path.join(part))
+}
+
+/// Downloads the tarball of a resolved package with the credentials
+/// `npmrc` has for it, retrying according to `retry`
+pub async fn download(
+    metadata: &Metadata,
+    npmrc: &Npmrc,
+    retry: &RetryPolicy,
+) -> Result<Vec<u8>, Error> {
+    download_url(&metadata.name, &metadata.dist.tarball, npmrc, retry, None).await
+}
+
+/// Downloads the tarball of `name` at `url`, like [download], waiting for
+/// a permit of `limit` (if given) while a request is in flight
+pub(crate) async fn download_url(
+    name: &str,
+    url: &str,
+    npmrc: &Npmrc,
+    retry: &RetryPolicy,
+    limit: Option<&Semaphore>,
+) -> Result<Vec<u8>, Error> {
+    let authorization = npmrc.authorization(name, url);
+    let request = || match &authorization {
+        Some(authorization) => CLIENT.get(url).header(AUTHORIZATION, authorization),
+        None => CLIENT.get(url),
+    };
+    let (response, _permit) = retry::send(request, name, url, retry, limit).await?;
+    if !response.status().is_success() {
+        return Err(Error::HttpStatus {
+            name: name.to_string(),
+            url: url.to_string(),
+            status: response.status().as_u16(),
+        });
+    }
+
+    match response.bytes().await {
+        Ok(bytes) => Ok(bytes.to_vec()),
+        Err(e) => Err(Error::Network {
+            name: name.to_string(),
+            url: url.to_string(),
+            cause: retry::cause(&e),
+        }),
+    }
+}
+
+/// Removes the single root directory every npm tarball wraps its files in
+/// (usually `package/`), refusing paths that would escape the destination
+pub(crate) fn strip_root(path: &Path) -> Option<PathBuf> {
+    let mut components = path.components();
+    components.next()?;
+
+    let mut stripped = PathBuf::new();
+    for component in components {
+        match component {
+            Component::Normal(part) => stripped.push(part),
+            Component::CurDir => {}
+            _ => return None,
+        }
+    }
+
+    if stripped.as_os_str().is_empty() {
+        None
+    } else {
+        Some(stripped)
+    }
+}
+
+/// Unpacks a gzipped package tarball into `destination`, replacing whatever
+/// was there before. Files are first unpacked into a staging directory next
+/// to `destination`, so a failed extraction never leaves half a package behind
+pub fn extract(tarball: &[u8], destination: &Path) -> Result<(), Error> {
+    stage(destination, |staging| unpack(tarball, staging))
+}
+
+/// Replaces `destination` with a directory filled by `fill`, which is
+/// handed a staging directory next to `destination`
+pub(crate) fn stage(
+    destination: &Path,
+    fill: impl FnOnce(&Path) -> Result<(), Error>,
+) -> Result<(), Error> {
+    let parent = destination.parent().ok_or(Error::IoError)?;
+    fs::create_dir_all(parent).map_err(|_| Error::IoError)?;
+    let staging = TempDir::new_in(parent, ".vortex-staging").map_err(|_| Error::IoError)?;
+
+    fill(staging.path())?;
+
+    if destination.exists() {
+        fs::remove_dir_all(destination).map_err(|_| Error::IoError)?;
+    }
+    fs::rename(staging.path(), destination).map_err(|_| Error::IoError)?;
+
+    // The staging directory has been moved, so there is nothing left to clean up
+    let _ = staging.into_path();
+    Ok(())
+}
+
+fn unpack(tarball: impl Read, destination: &Path) -> Result<(), Error> {
+    let mut archive = Archive::new(GzDecoder::new(tarball));
+    let entries = archive.entries().map_err(|_| Error::InvalidTarball)?;
+
+    for entry in entries {
+        let mut entry = entry.map_err(|_| Error::InvalidTarball)?;
+        let path = match strip_root(&entry.path().map_err(|_| Error::InvalidTarball)?) {
+            Some(path) => destination.join(path),
+            None => continue,
+        };
+
+        match entry.header().entry_type() {
+            EntryType::Directory => fs::create_dir_all(&path).map_err(|_| Error::IoError)?,
+            EntryType::Regular | EntryType::Continuous => {
+                if let Some(parent) = path.parent() {
+                    fs::create_dir_all(parent).map_err(|_| Error::IoError)?;
+                }
+                entry.unpack(&path).map_err(|_| Error::IoError)?;
+            }
+            // npm ignores links and devices inside package tarballs
+            _ => continue,
+        }
+    }
+
+    Ok(())
+}
+
+/// A regular file of a package tarball
+pub(crate) struct File {
+    /// Relative to the package root
+    pub path: PathBuf,
+    pub contents: Vec<u8>,
+    pub executable: bool,
+}
+
+/// Reads every regular file of a gzipped package tarball into memory
+pub(crate) fn files(tarball: &[u8]) -> Result<Vec<File>, Error> {
+    let mut archive = Archive::new(GzDecoder::new(tarball));
+    let entries = archive.entries().map_err(|_| Error::InvalidTarball)?;
+
+    let mut files = Vec::new();
+    for entry in entries {
+        let mut entry = entry.map_err(|_| Error::InvalidTarball)?;
+        if !matches!(
+            entry.header().entry_type(),
+            EntryType::Regular | EntryType::Continuous
+        ) {
+            continue;
+        }
+        let path = match strip_root(&entry.path().map_err(|_| Error::InvalidTarball)?) {
+            Some(path) => path,
+            None => continue,
+        };
+
+        let executable = entry.header().mode().unwrap_or(0o644) & 0o111 != 0;
+        let mut contents = Vec::new();
+        entry
+            .read_to_end(&mut contents)
+            .map_err(|_| Error::InvalidTarball)?;
+        files.push(File {
+            path,
+            contents,
+            executable,
+        });
+    }
+
+    Ok(files)
+}
+
+/// Downloads a resolved package with the credentials `npmrc` has for it,
+/// checks its integrity and extracts it into `destination`. Packages locked
+/// to a git commit can't be downloaded, and are installed by a [crate::Store]
+/// from the cache instead
+pub async fn install_package(
+    metadata: &Metadata,
+    npmrc: &Npmrc,
+    destination: &Path,
+) -> Result<(), Error> {
+    if let Some((url, _)) = git::parse_resolved(&metadata.name, &metadata.dist.tarball) {
+        return Err(Error::Git {
+            name: metadata.name.clone(),
+            url,
+            cause: String::from("git packages are only installed from a cache, and there is none"),
+        });
+    }
+    let tarball = match local::parse_resolved(&metadata.name, &metadata.dist.tarball) {
+        Some(Local::Link(target)) => return local::link(&metadata.name, &target, destination),
+        Some(Local::Directory(dir)) => pack(&dir)?,
+        Some(local) => {
+            let tarball = local.tarball(&metadata.name)?;
+            verify(&metadata.name, &metadata.dist, &tarball)?;
+            tarball
+        }
+        None => {
+            let tarball = download(metadata, npmrc, &RetryPolicy::default()).await?;
+            verify(&metadata.name, &metadata.dist, &tarball)?;
+            tarball
+        }
+    };
+    extract(&tarball, destination)
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use flate2::{write::GzEncoder, Compression};
+    use tar::{Builder, Header};
+
+    /// Builds a gzipped tarball containing `files`, like `npm pack` would
+    pub(crate) fn tarball(files: &[(&str, &str)]) -> Vec<u8> {
+        let mut builder = Builder::new(GzEncoder::new(Vec::new(), Compression::default()));
+        for (path, contents) in files {
+            let mut header = Header::new_gnu();
+            header.set_size(contents.len() as u64);
+            header.set_mode(0o644);
+            header.set_cksum();
+            builder
+                .append_data(&mut header, path, contents.as_bytes())
+                .unwrap();
+        }
+        builder.into_inner().unwrap().finish().unwrap()
+    }
+
+    #[test]
+    fn scoped_package_dir() {
+        assert_eq!(
+            package_dir(Path::new("node_modules"), "@types/node"),
+            Path::new("node_modules").join("@types").join("node")
+        );
+    }
+
+    #[test]
+    fn strips_any_root() {
+        assert_eq!(
+            strip_root(Path::new("package/lib/index.js")),
+            Some(PathBuf::from("lib/index.js"))
+        );
+        assert_eq!(
+            strip_root(Path::new("node/index.js")),
+            Some(PathBuf::from("index.js"))
+        );
+        assert_eq!(strip_root(Path::new("package")), None);
+        assert_eq!(strip_root(Path::new("package/../../etc/passwd")), None);
+    }
+
+    #[test]
+    fn extracts_into_node_modules() {
+        let dir = TempDir::new("vortex-extract").unwrap();
+        let destination = package_dir(&dir.path().join("node_modules"), "@scope/pkg");
+        let tarball = tarball(&[
+            ("package/package.json", "{\"name\":\"@scope/pkg\"}"),
+            ("package/lib/index.js", "module.exports = 1"),
+        ]);
+
+        extract(&tarball, &destination).unwrap();
+
+        assert_eq!(
+            fs::read_to_string(destination.join("lib").join("index.js")).unwrap(),
+            "module.exports = 1"
+        );
+        assert!(destination.join("package.json").is_file());
+    }
+
+    #[test]
+    fn refuses_to_download_git_packages() {
+        let dir = TempDir::new("vortex-extract").unwrap();
+        let mut metadata = crate::tests::metadata("b", "1.0.0", &[]);
+        metadata.dist.tarball = format!(
+            "git+https://127.0.0.1:1/b.git#{}",
+            "0123456789abcdef0123456789abcdef01234567"
+        );
+
+        let installed = tokio_test::block_on(install_package(
+            &metadata,
+            &Npmrc::new("https://registry.npmjs.org"),
+            &dir.path().join("b"),
+        ));
+
+        assert!(
+            matches!(installed, Err(Error::Git { url, .. }) if url == "https://127.0.0.1:1/b.git")
+        );
+        assert!(!dir.path().join("b").exists());
+    }
+}
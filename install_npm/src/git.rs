@@ -0,0 +1,369 @@
+use std::collections::HashMap;
+use std::path::Path;
+
+use node_semver::{Range, Version};
+use tokio::process::Command;
+
+use crate::{Error, GitReference, GitSpec, Spec};
+
+fn error(name: &str, url: &str, cause: impl Into<String>) -> Error {
+    Error::Git {
+        name: name.to_string(),
+        url: url.to_string(),
+        cause: cause.into(),
+    }
+}
+
+/// Runs the system `git` with `args` (in `dir`, if given) and returns what
+/// it printed. `name` and `url` are only there for errors
+async fn git(name: &str, url: &str, dir: Option<&Path>, args: &[&str]) -> Result<String, Error> {
+    let mut command = Command::new("git");
+    if let Some(dir) = dir {
+        command.current_dir(dir);
+    }
+    let output = command
+        .args(args)
+        // Fail instead of waiting for a password nobody will type
+        .env("GIT_TERMINAL_PROMPT", "0")
+        .output()
+        .await
+        .map_err(|e| error(name, url, format!("failed to run git: {e}")))?;
+
+    if output.status.success() {
+        Ok(String::from_utf8_lossy(&output.stdout).to_string())
+    } else {
+        Err(error(
+            name,
+            url,
+            String::from_utf8_lossy(&output.stderr).trim(),
+        ))
+    }
+}
+
+/// Whether `reference` is a full commit SHA
+pub(crate) fn is_commit(reference: &str) -> bool {
+    reference.len() == 40 && reference.chars().all(|c| c.is_ascii_hexdigit())
+}
+
+/// Asks the remote which commit `spec` points at, without cloning it.
+/// `None` means only a clone can tell, which is the case for abbreviated
+/// commits
+pub(crate) async fn resolve_commit(name: &str, spec: &GitSpec) -> Result<Option<String>, Error> {
+    let url = &spec.url;
+    if let GitReference::Committish(committish) = &spec.reference {
+        if is_commit(committish) {
+            return Ok(Some(committish.to_lowercase()));
+        }
+    }
+
+    // URLs come from package.json, and mustn't be taken for options
+    let output = git(name, url, None, &["ls-remote", "--", url]).await?;
+    let mut refs: HashMap<&str, &str> = HashMap::new();
+    for line in output.lines() {
+        if let Some((sha, reference)) = line.split_once('\t') {
+            // Annotated tags are listed twice, and `tag^{}` is the commit
+            match reference.strip_suffix("^{}") {
+                Some(tag) => {
+                    refs.insert(tag, sha);
+                }
+                None => {
+                    refs.entry(reference).or_insert(sha);
+                }
+            }
+        }
+    }
+
+    let sha = match &spec.reference {
+        GitReference::Head => refs.get("HEAD").copied(),
+        GitReference::Committish(committish) => {
+            let sha = [
+                format!("refs/tags/{committish}"),
+                format!("refs/heads/{committish}"),
+                committish.clone(),
+            ]
+            .iter()
+            .find_map(|reference| refs.get(reference.as_str()).copied());
+            if sha.is_none() && committish.chars().all(|c| c.is_ascii_hexdigit()) {
+                return Ok(None);
+            }
+            sha
+        }
+        GitReference::Semver(range) => {
+            let range = Range::parse(range)
+                .map_err(|_| error(name, url, format!("{range} isn't a valid range")))?;
+            refs.iter()
+                .filter_map(|(reference, sha)| {
+                    let tag = reference.strip_prefix("refs/tags/")?;
+                    let version = Version::parse(tag.trim_start_matches('v')).ok()?;
+                    range.satisfies(&version).then_some((version, *sha))
+                })
+                .max_by(|(a, _), (b, _)| a.cmp(b))
+                .map(|(_, sha)| sha)
+        }
+    };
+
+    match sha {
+        Some(sha) => Ok(Some(sha.to_string())),
+        None => Err(error(
+            name,
+            url,
+            match &spec.reference {
+                GitReference::Head => String::from("the repository has no HEAD"),
+                GitReference::Committish(committish) => {
+                    format!("there is no branch, tag or commit called {committish}")
+                }
+                GitReference::Semver(range) => format!("no tag satisfies {range}"),
+            },
+        )),
+    }
+}
+
+/// Clones `url` into `dir` and checks out `reference`, returning the SHA
+/// of the commit it points at
+pub(crate) async fn checkout(
+    name: &str,
+    url: &str,
+    reference: &str,
+    dir: &Path,
+) -> Result<String, Error> {
+    // `checkout` takes whatever comes after `--` for paths, so references
+    // that look like options are refused instead
+    if reference.starts_with('-') {
+        return Err(error(
+            name,
+            url,
+            format!("{reference} isn't a valid reference"),
+        ));
+    }
+
+    let dir_name = dir.to_string_lossy();
+    git(
+        name,
+        url,
+        None,
+        &["clone", "--quiet", "--no-checkout", "--", url, &dir_name],
+    )
+    .await?;
+    git(
+        name,
+        url,
+        Some(dir),
+        &["checkout", "--quiet", reference, "--"],
+    )
+    .await?;
+    Ok(git(name, url, Some(dir), &["rev-parse", "HEAD"])
+        .await?
+        .trim()
+        .to_string())
+}
+
+/// Runs the `prepare` script of the package in `dir`, which builds packages
+/// that don't commit their build output
+pub(crate) async fn prepare(name: &str, url: &str, dir: &Path, script: &str) -> Result<(), Error> {
+    let bin = dir.join("node_modules").join(".bin");
+    let paths = std::env::var_os("PATH")
+        .map(|path| std::env::split_paths(&path).collect::<Vec<_>>())
+        .unwrap_or_default();
+    let path = std::env::join_paths(std::iter::once(bin).chain(paths))
+        .map_err(|e| error(name, url, e.to_string()))?;
+
+    let (shell, flag) = if cfg!(target_os = "windows") {
+        ("cmd", "/c")
+    } else {
+        ("sh", "-c")
+    };
+    let output = Command::new(shell)
+        .arg(flag)
+        .arg(script)
+        .current_dir(dir)
+        .env("PATH", path)
+        .output()
+        .await
+        .map_err(|e| error(name, url, format!("failed to run its prepare script: {e}")))?;
+
+    if output.status.success() {
+        Ok(())
+    } else {
+        Err(error(
+            name,
+            url,
+            format!(
+                "its prepare script failed: {}",
+                String::from_utf8_lossy(&output.stderr).trim()
+            ),
+        ))
+    }
+}
+
+/// How a package resolved from git is recorded, like npm does:
+/// `git+https://host/repo.git#<sha>`
+pub(crate) fn resolved_url(url: &str, sha: &str) -> String {
+    if url.starts_with("git://") {
+        return format!("{url}#{sha}");
+    }
+    // `git@host:path` is how ssh URLs are written for `git` itself
+    match url.split_once(':') {
+        Some((host, path)) if !url.contains("://") => format!("git+ssh://{host}/{path}#{sha}"),
+        _ => format!("git+{url}#{sha}"),
+    }
+}
+
+/// The repository and commit of a package resolved from git, if it was
+pub(crate) fn parse_resolved(name: &str, resolved: &str) -> Option<(String, String)> {
+    match Spec::parse(name, resolved) {
+        Ok(Spec::Git(GitSpec {
+            url,
+            reference: GitReference::Committish(sha),
+        })) if is_commit(&sha) => Some((url, sha)),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use std::fs;
+    use tempdir::TempDir;
+
+    /// Runs `git` in `dir` for a test, which fails if it does
+    pub(crate) fn run(dir: &Path, args: &[&str]) -> String {
+        tokio_test::block_on(git("test", "test", Some(dir), args)).unwrap()
+    }
+
+    /// A bare repository with a commit per `(tag, files)`, each tagged (and
+    /// each replacing the files of the one before), returning its URL
+    pub(crate) fn repository(dir: &Path, commits: &[(&str, &[(&str, &str)])]) -> String {
+        let work = dir.join("work");
+        fs::create_dir_all(&work).unwrap();
+        run(&work, &["init", "--quiet", "--initial-branch=main"]);
+        for (tag, files) in commits {
+            for (path, contents) in *files {
+                let path = work.join(path);
+                fs::create_dir_all(path.parent().unwrap()).unwrap();
+                fs::write(path, contents).unwrap();
+            }
+            run(&work, &["add", "--all"]);
+            run(
+                &work,
+                &[
+                    "-c",
+                    "user.name=test",
+                    "-c",
+                    "user.email=test@example.com",
+                    "commit",
+                    "--quiet",
+                    "--message",
+                    tag,
+                ],
+            );
+            run(&work, &["tag", tag]);
+        }
+
+        let bare = dir.join("repo.git");
+        run(
+            dir,
+            &[
+                "clone",
+                "--quiet",
+                "--bare",
+                &work.to_string_lossy(),
+                &bare.to_string_lossy(),
+            ],
+        );
+        bare.to_string_lossy().to_string()
+    }
+
+    fn spec(url: &str, reference: GitReference) -> GitSpec {
+        GitSpec {
+            url: url.to_string(),
+            reference,
+        }
+    }
+
+    #[test]
+    fn resolves_references() {
+        let dir = TempDir::new("vortex-git").unwrap();
+        let url = repository(
+            dir.path(),
+            &[
+                ("v1.0.0", &[("index.js", "1")]),
+                ("v1.1.0", &[("index.js", "2")]),
+                ("v2.0.0", &[("index.js", "3")]),
+            ],
+        );
+        let commit = |reference: &str| run(Path::new(&url), &["rev-parse", reference]);
+        let resolve = |reference| tokio_test::block_on(resolve_commit("a", &spec(&url, reference)));
+
+        assert_eq!(
+            resolve(GitReference::Head).unwrap(),
+            Some(commit("v2.0.0").trim().to_string())
+        );
+        assert_eq!(
+            resolve(GitReference::Semver(String::from("^1.0.0"))).unwrap(),
+            Some(commit("v1.1.0").trim().to_string())
+        );
+        assert_eq!(
+            resolve(GitReference::Committish(String::from("v1.0.0"))).unwrap(),
+            Some(commit("v1.0.0").trim().to_string())
+        );
+        assert_eq!(
+            resolve(GitReference::Committish(String::from("abc123"))).unwrap(),
+            None
+        );
+        assert!(resolve(GitReference::Semver(String::from("^3.0.0"))).is_err());
+    }
+
+    #[test]
+    fn checks_out_commits() {
+        let dir = TempDir::new("vortex-git").unwrap();
+        let url = repository(
+            dir.path(),
+            &[
+                ("v1.0.0", &[("index.js", "1")]),
+                ("v2.0.0", &[("index.js", "2")]),
+            ],
+        );
+        let checkout_dir = dir.path().join("checkout");
+
+        let sha = tokio_test::block_on(checkout("a", &url, "v1.0.0", &checkout_dir)).unwrap();
+
+        assert_eq!(sha, run(Path::new(&url), &["rev-parse", "v1.0.0"]).trim());
+        assert_eq!(
+            fs::read_to_string(checkout_dir.join("index.js")).unwrap(),
+            "1"
+        );
+        assert!(tokio_test::block_on(checkout(
+            "a",
+            &url,
+            "--upload-pack=touch pwned",
+            &dir.path().join("other")
+        ))
+        .is_err());
+    }
+
+    #[test]
+    fn records_resolved_urls() {
+        let sha = "0123456789abcdef0123456789abcdef01234567";
+        for (url, resolved) in [
+            (
+                "https://github.com/user/repo.git",
+                format!("git+https://github.com/user/repo.git#{sha}"),
+            ),
+            (
+                "git@github.com:user/repo.git",
+                format!("git+ssh://git@github.com/user/repo.git#{sha}"),
+            ),
+            (
+                "git://example.com/repo.git",
+                format!("git://example.com/repo.git#{sha}"),
+            ),
+        ] {
+            assert_eq!(resolved_url(url, sha), resolved);
+            assert!(parse_resolved("a", &resolved).is_some_and(|(_, commit)| commit == sha));
+        }
+        assert_eq!(
+            parse_resolved("a", "https://registry.npmjs.org/a/-/a-1.0.0.tgz"),
+            None
+        );
+    }
+}
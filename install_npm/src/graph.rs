@@ -0,0 +1,374 @@
+use std::collections::{BTreeMap, HashSet};
+use std::fmt::{self, Display};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{Host, Metadata};
+
+/// Identifies a resolved package by its name and exact version
+#[derive(Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Debug)]
+pub struct PackageId {
+    pub name: String,
+    pub version: String,
+}
+
+impl PackageId {
+    pub fn new(name: &str, version: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            version: version.to_string(),
+        }
+    }
+}
+
+impl From<&Metadata> for PackageId {
+    fn from(metadata: &Metadata) -> Self {
+        Self::new(&metadata.name, &metadata.version)
+    }
+}
+
+impl Display for PackageId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}@{}", self.name, self.version)
+    }
+}
+
+/// Which package.json map a dependency was declared in
+#[derive(
+    Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Debug, Default, Serialize, Deserialize,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum DependencyKind {
+    #[default]
+    Prod,
+    Dev,
+    Optional,
+    Peer,
+}
+
+impl Display for DependencyKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DependencyKind::Prod => write!(f, "prod"),
+            DependencyKind::Dev => write!(f, "dev"),
+            DependencyKind::Optional => write!(f, "optional"),
+            DependencyKind::Peer => write!(f, "peer"),
+        }
+    }
+}
+
+/// A dependency as written in a package.json, before it is resolved
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Dependency {
+    pub name: String,
+    pub range: String,
+    pub kind: DependencyKind,
+}
+
+impl Dependency {
+    pub fn new(name: &str, range: &str, kind: DependencyKind) -> Self {
+        Self {
+            name: name.to_string(),
+            range: range.to_string(),
+            kind,
+        }
+    }
+}
+
+/// A resolved dependency: the package that `name@range` pointed to
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Edge {
+    pub name: String,
+    pub range: String,
+    pub kind: DependencyKind,
+    pub to: PackageId,
+    /// What the edge was resolved with instead of `range`, when one of the
+    /// project's overrides applies to it
+    pub overridden: Option<String>,
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct Node {
+    pub metadata: Metadata,
+    /// Outgoing edges, sorted by dependency name
+    pub edges: Vec<Edge>,
+}
+
+/// Every resolved package, keyed by `name@version`, along with the edges
+/// between them. The project itself is not a node; its dependencies are
+/// the graph's roots. Cycles are represented as edges back to an existing node
+#[derive(Clone, PartialEq, Debug, Default)]
+pub struct Graph {
+    roots: Vec<Edge>,
+    nodes: BTreeMap<PackageId, Node>,
+}
+
+impl Graph {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The project's own dependencies, sorted by name
+    pub fn roots(&self) -> &[Edge] {
+        &self.roots
+    }
+
+    pub fn node(&self, id: &PackageId) -> Option<&Node> {
+        self.nodes.get(id)
+    }
+
+    pub fn contains(&self, id: &PackageId) -> bool {
+        self.nodes.contains_key(id)
+    }
+
+    /// Every node, in `name@version` order
+    pub fn nodes(&self) -> impl Iterator<Item = (&PackageId, &Node)> {
+        self.nodes.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    /// Adds a resolved package, returning false if it was already in the graph
+    pub fn add_node(&mut self, metadata: Metadata) -> bool {
+        let id = PackageId::from(&metadata);
+        if self.nodes.contains_key(&id) {
+            return false;
+        }
+
+        self.nodes.insert(
+            id,
+            Node {
+                metadata,
+                edges: Vec::new(),
+            },
+        );
+        true
+    }
+
+    /// Adds an edge from `from`, or from the project itself if `from` is `None`
+    pub fn add_edge(&mut self, from: Option<&PackageId>, edge: Edge) {
+        let edges = match from {
+            Some(from) => match self.nodes.get_mut(from) {
+                Some(node) => &mut node.edges,
+                None => return,
+            },
+            None => &mut self.roots,
+        };
+
+        if let Err(index) = edges.binary_search_by(|e| e.name.cmp(&edge.name)) {
+            edges.insert(index, edge);
+        }
+    }
+
+    /// Every package reachable from the roots without following an edge
+    /// of one of the `skipped` kinds. Packages missing from
+    /// `reachable(&[DependencyKind::Dev])` are only needed for development
+    pub fn reachable(&self, skipped: &[DependencyKind]) -> HashSet<&PackageId> {
+        let mut reachable: HashSet<&PackageId> = HashSet::new();
+        let mut stack: Vec<&Edge> = self.roots.iter().collect();
+
+        while let Some(edge) = stack.pop() {
+            if skipped.contains(&edge.kind) || !reachable.insert(&edge.to) {
+                continue;
+            }
+            if let Some(node) = self.nodes.get(&edge.to) {
+                stack.extend(node.edges.iter());
+            }
+        }
+
+        reachable
+    }
+
+    /// The graph without `excluded`, and without the packages only they
+    /// depended on
+    pub fn without(&self, excluded: &HashSet<&PackageId>) -> Graph {
+        let kept = |edge: &&Edge| !excluded.contains(&edge.to);
+        let mut graph = Graph {
+            roots: self.roots.iter().filter(kept).cloned().collect(),
+            nodes: BTreeMap::new(),
+        };
+
+        let mut stack: Vec<&PackageId> = self
+            .roots
+            .iter()
+            .filter(kept)
+            .map(|edge| &edge.to)
+            .collect();
+        while let Some(id) = stack.pop() {
+            let node = match self.nodes.get(id) {
+                Some(node) if !graph.nodes.contains_key(id) => node,
+                _ => continue,
+            };
+            stack.extend(node.edges.iter().filter(kept).map(|edge| &edge.to));
+            let edges = node.edges.iter().filter(kept).cloned().collect();
+            graph.nodes.insert(
+                id.clone(),
+                Node {
+                    metadata: node.metadata.clone(),
+                    edges,
+                },
+            );
+        }
+        graph
+    }
+
+    /// The graph without the optional packages `host` doesn't support, like
+    /// the platform-specific binaries of esbuild for other platforms.
+    /// Packages something needs regardless are kept either way
+    pub fn supported_by(&self, host: &Host) -> Graph {
+        let required = self.reachable(&[DependencyKind::Optional]);
+        let unsupported: HashSet<&PackageId> = self
+            .nodes
+            .iter()
+            .filter(|(id, node)| !required.contains(id) && !node.metadata.platform.supports(host))
+            .map(|(id, _)| id)
+            .collect();
+        self.without(&unsupported)
+    }
+
+    /// Every edge pointing at `id`, along with the package it comes from
+    /// (`None` for the project itself)
+    pub fn dependents(&self, id: &PackageId) -> Vec<(Option<&PackageId>, &Edge)> {
+        let roots = self.roots.iter().map(|edge| (None, edge));
+        let nodes = self
+            .nodes
+            .iter()
+            .flat_map(|(from, node)| node.edges.iter().map(move |edge| (Some(from), edge)));
+
+        roots
+            .chain(nodes)
+            .filter(|(_, edge)| &edge.to == id)
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Dist, Platform};
+
+    fn metadata(name: &str, version: &str) -> Metadata {
+        Metadata {
+            name: name.to_string(),
+            version: version.to_string(),
+            dependencies: None,
+            peer_dependencies: None,
+            peer_dependencies_meta: None,
+            optional_dependencies: None,
+            platform: Platform::default(),
+            dist: Dist {
+                tarball: String::new(),
+                shasum: String::new(),
+                integrity: None,
+            },
+        }
+    }
+
+    fn edge(name: &str, range: &str, to: &str) -> Edge {
+        Edge {
+            name: name.to_string(),
+            range: range.to_string(),
+            kind: DependencyKind::Prod,
+            to: PackageId::new(name, to),
+            overridden: None,
+        }
+    }
+
+    #[test]
+    fn nodes_are_unique() {
+        let mut graph = Graph::new();
+        assert!(graph.add_node(metadata("a", "1.0.0")));
+        assert!(!graph.add_node(metadata("a", "1.0.0")));
+        assert!(graph.add_node(metadata("a", "2.0.0")));
+        assert_eq!(graph.len(), 2);
+    }
+
+    #[test]
+    fn reachable_skips_kinds() {
+        let mut graph = Graph::new();
+        let a = PackageId::new("a", "1.0.0");
+        let b = PackageId::new("b", "1.0.0");
+        graph.add_node(metadata("a", "1.0.0"));
+        graph.add_node(metadata("b", "1.0.0"));
+        graph.add_edge(None, edge("a", "^1.0.0", "1.0.0"));
+        graph.add_edge(
+            None,
+            Edge {
+                kind: DependencyKind::Dev,
+                ..edge("b", "^1.0.0", "1.0.0")
+            },
+        );
+        graph.add_edge(Some(&b), edge("a", "*", "1.0.0"));
+
+        assert_eq!(graph.reachable(&[]), HashSet::from([&a, &b]));
+        assert_eq!(graph.reachable(&[DependencyKind::Dev]), HashSet::from([&a]));
+    }
+
+    #[test]
+    fn dependents_include_cycles() {
+        let mut graph = Graph::new();
+        let a = PackageId::new("a", "1.0.0");
+        let b = PackageId::new("b", "1.0.0");
+        graph.add_node(metadata("a", "1.0.0"));
+        graph.add_node(metadata("b", "1.0.0"));
+        graph.add_edge(None, edge("a", "^1.0.0", "1.0.0"));
+        graph.add_edge(Some(&a), edge("b", "^1.0.0", "1.0.0"));
+        graph.add_edge(Some(&b), edge("a", "*", "1.0.0"));
+
+        assert_eq!(
+            graph.dependents(&a),
+            vec![
+                (None, &edge("a", "^1.0.0", "1.0.0")),
+                (Some(&b), &edge("a", "*", "1.0.0")),
+            ]
+        );
+    }
+
+    #[test]
+    fn skips_unsupported_optional_packages() {
+        let mut graph = Graph::new();
+        let esbuild = PackageId::new("esbuild", "0.19.0");
+        let linux = PackageId::new("@esbuild/linux-x64", "0.19.0");
+        let darwin = PackageId::new("@esbuild/darwin-arm64", "0.19.0");
+        let fsevents = PackageId::new("fsevents", "2.3.2");
+        let only_darwin = |mut metadata: Metadata| {
+            metadata.platform.os = Some(vec![String::from("darwin")]);
+            metadata
+        };
+        graph.add_node(metadata("esbuild", "0.19.0"));
+        graph.add_node(metadata("@esbuild/linux-x64", "0.19.0"));
+        graph.add_node(only_darwin(metadata("@esbuild/darwin-arm64", "0.19.0")));
+        graph.add_node(only_darwin(metadata("fsevents", "2.3.2")));
+        graph.add_edge(None, edge("esbuild", "^0.19.0", "0.19.0"));
+        // Something needs fsevents whatever the platform
+        graph.add_edge(None, edge("fsevents", "^2.0.0", "2.3.2"));
+        for id in [&linux, &darwin, &fsevents] {
+            graph.add_edge(
+                Some(&esbuild),
+                Edge {
+                    kind: DependencyKind::Optional,
+                    ..edge(&id.name, "*", &id.version)
+                },
+            );
+        }
+        let host = Host {
+            os: String::from("linux"),
+            cpu: String::from("x64"),
+            libc: Some(String::from("glibc")),
+        };
+
+        let supported = graph.supported_by(&host);
+
+        assert_eq!(
+            supported.nodes().map(|(id, _)| id).collect::<Vec<_>>(),
+            vec![&linux, &esbuild, &fsevents]
+        );
+        assert_eq!(supported.node(&esbuild).unwrap().edges.len(), 2);
+    }
+}
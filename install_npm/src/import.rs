@@ -0,0 +1,280 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt::{self, Display};
+
+use crate::{Dependency, DependencyKind, Edge, Graph, Metadata, PackageId};
+
+/// A lockfile entry that can't be turned into a registry package
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Unmapped {
+    pub name: String,
+    pub spec: String,
+    pub reason: &'static str,
+}
+
+impl Display for Unmapped {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}@{} ({})", self.name, self.spec, self.reason)
+    }
+}
+
+/// Another package manager's lockfile, converted into a [Graph].
+///
+/// Packages that can't be mapped are left out of the graph, along with
+/// every package depending on them, so the next install resolves them
+/// from the registry instead of installing an incomplete tree
+#[derive(Clone, PartialEq, Debug)]
+pub struct Import {
+    pub graph: Graph,
+    pub unmapped: Vec<Unmapped>,
+    /// Packages left out because they depend on an unmapped entry
+    pub dropped: Vec<PackageId>,
+}
+
+/// Why a dependency spec can't be pinned to a registry package, if it can't
+pub(crate) fn unmappable(spec: &str) -> Option<&'static str> {
+    let protocols = [
+        ("git", "git dependency"),
+        ("github:", "git dependency"),
+        ("gitlab:", "git dependency"),
+        ("bitbucket:", "git dependency"),
+        ("patch:", "patched package"),
+        ("file:", "local package"),
+        ("link:", "linked package"),
+        ("portal:", "linked package"),
+        ("workspace:", "workspace package"),
+        ("npm:", "aliased package"),
+        ("http:", "tarball URL"),
+        ("https:", "tarball URL"),
+    ];
+
+    match protocols
+        .iter()
+        .find(|(protocol, _)| spec.starts_with(protocol))
+    {
+        Some((_, reason)) => Some(reason),
+        // Semver ranges and tags never contain slashes, so this is a
+        // `user/repo` GitHub shorthand
+        None if spec.contains('/') => Some("git dependency"),
+        None => None,
+    }
+}
+
+/// Splits a `name@spec` lockfile key, keeping the leading `@` of scoped
+/// packages
+pub(crate) fn split_key(key: &str) -> Option<(&str, &str)> {
+    let index = key.get(1..)?.find('@')? + 1;
+    Some((&key[..index], &key[index + 1..]))
+}
+
+/// The dependencies of a pinned package with the kind of edge each gets.
+/// Optional dependencies win over regular ones of the same name, like the
+/// resolver has them
+fn dependencies(metadata: &Metadata) -> BTreeMap<&String, (&String, DependencyKind)> {
+    let mut dependencies: BTreeMap<_, _> = metadata
+        .dependencies
+        .iter()
+        .flatten()
+        .map(|(name, spec)| (name, (spec, DependencyKind::Prod)))
+        .collect();
+    for (name, spec) in metadata.optional_dependencies.iter().flatten() {
+        dependencies.insert(name, (spec, DependencyKind::Optional));
+    }
+    dependencies
+}
+
+/// Packages pinned by a lockfile, before they're linked into a [Graph]
+#[derive(Default)]
+pub(crate) struct Pins {
+    /// What each `(name, spec)` dependency was pinned to. A package's
+    /// dependencies (in [Metadata::dependencies] and
+    /// [Metadata::optional_dependencies]) are looked up here
+    pub requests: HashMap<(String, String), PackageId>,
+    pub packages: BTreeMap<PackageId, Metadata>,
+    pub unmapped: Vec<Unmapped>,
+}
+
+impl Pins {
+    /// Links the pinned packages into a [Graph] whose roots are `roots`,
+    /// each paired with the spec it was pinned under (which isn't always
+    /// the range in package.json)
+    pub fn into_import(mut self, roots: &[(Dependency, String)]) -> Import {
+        // Mark everything that can't be installed completely, until nothing
+        // changes, as dependency cycles can run through unmapped packages
+        let mut dropped: HashSet<PackageId> = HashSet::new();
+        loop {
+            let before = dropped.len();
+            for (id, metadata) in &self.packages {
+                if dropped.contains(id) {
+                    continue;
+                }
+                let complete =
+                    dependencies(metadata).into_iter().all(|(name, (spec, _))| {
+                        match self.requests.get(&(name.clone(), spec.clone())) {
+                            Some(to) => !dropped.contains(to),
+                            None => false,
+                        }
+                    });
+                if !complete {
+                    dropped.insert(id.clone());
+                }
+            }
+            if dropped.len() == before {
+                break;
+            }
+        }
+
+        let mut graph = Graph::new();
+        for (id, metadata) in &self.packages {
+            if !dropped.contains(id) {
+                graph.add_node(metadata.clone());
+            }
+        }
+        for (id, metadata) in &self.packages {
+            if dropped.contains(id) {
+                continue;
+            }
+            for (name, (spec, kind)) in dependencies(metadata) {
+                let to = self.requests[&(name.clone(), spec.clone())].clone();
+                graph.add_edge(
+                    Some(id),
+                    Edge {
+                        name: name.clone(),
+                        range: spec.clone(),
+                        kind,
+                        to,
+                        overridden: None,
+                    },
+                );
+            }
+        }
+
+        for (dependency, spec) in roots {
+            let key = (dependency.name.clone(), spec.clone());
+            match self.requests.get(&key) {
+                Some(to) if !dropped.contains(to) => graph.add_edge(
+                    None,
+                    Edge {
+                        name: dependency.name.clone(),
+                        range: dependency.range.clone(),
+                        kind: dependency.kind,
+                        to: to.clone(),
+                        overridden: None,
+                    },
+                ),
+                Some(_) => {}
+                None => {
+                    let reported = self
+                        .unmapped
+                        .iter()
+                        .any(|unmapped| unmapped.name == key.0 && unmapped.spec == key.1);
+                    if !reported {
+                        self.unmapped.push(Unmapped {
+                            name: key.0,
+                            spec: key.1,
+                            reason: "missing from the lockfile",
+                        });
+                    }
+                }
+            }
+        }
+
+        let mut dropped: Vec<PackageId> = dropped.into_iter().collect();
+        dropped.sort();
+        Import {
+            graph,
+            unmapped: self.unmapped,
+            dropped,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::metadata;
+
+    #[test]
+    fn classifies_specs() {
+        assert_eq!(unmappable("^1.0.0"), None);
+        assert_eq!(unmappable("latest"), None);
+        assert_eq!(
+            unmappable("git+https://github.com/a/b.git"),
+            Some("git dependency")
+        );
+        assert_eq!(unmappable("user/repo#v1"), Some("git dependency"));
+        assert_eq!(
+            unmappable("patch:a@1.0.0#./a.patch"),
+            Some("patched package")
+        );
+        assert_eq!(unmappable("workspace:*"), Some("workspace package"));
+    }
+
+    #[test]
+    fn splits_scoped_keys() {
+        assert_eq!(split_key("a@^1.0.0"), Some(("a", "^1.0.0")));
+        assert_eq!(split_key("@types/a@npm:b@1"), Some(("@types/a", "npm:b@1")));
+        assert_eq!(split_key("a"), None);
+    }
+
+    #[test]
+    fn drops_dependents_of_unmapped() {
+        let mut pins = Pins::default();
+        for (name, version, deps) in [
+            ("a", "1.0.0", vec![("b", "^1.0.0")]),
+            ("b", "1.0.0", vec![("c", "github:c/c")]),
+            ("d", "1.0.0", vec![]),
+        ] {
+            let metadata = metadata(name, version, &deps);
+            pins.requests.insert(
+                (name.to_string(), String::from("^1.0.0")),
+                PackageId::from(&metadata),
+            );
+            pins.packages.insert(PackageId::from(&metadata), metadata);
+        }
+        pins.unmapped.push(Unmapped {
+            name: String::from("c"),
+            spec: String::from("github:c/c"),
+            reason: "git dependency",
+        });
+
+        let import = pins.into_import(&[
+            (
+                Dependency::new("a", "^1.0.0", DependencyKind::Prod),
+                String::from("^1.0.0"),
+            ),
+            (
+                Dependency::new("d", "^1.0.0", DependencyKind::Dev),
+                String::from("^1.0.0"),
+            ),
+            (
+                Dependency::new("e", "^1.0.0", DependencyKind::Prod),
+                String::from("^1.0.0"),
+            ),
+        ]);
+
+        assert_eq!(
+            import
+                .graph
+                .roots()
+                .iter()
+                .map(|edge| &edge.to)
+                .collect::<Vec<_>>(),
+            vec![&PackageId::new("d", "1.0.0")]
+        );
+        assert_eq!(
+            import.dropped,
+            vec![PackageId::new("a", "1.0.0"), PackageId::new("b", "1.0.0")]
+        );
+        assert_eq!(
+            import
+                .unmapped
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>(),
+            vec![
+                "c@github:c/c (git dependency)",
+                "e@^1.0.0 (missing from the lockfile)"
+            ]
+        );
+    }
+}
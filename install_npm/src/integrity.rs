@@ -0,0 +1,122 @@
+use sha1::Sha1;
+use sha2::{Digest, Sha512};
+
+use crate::{Dist, Error};
+
+/// Computes the sha512 Subresource Integrity string of `data`,
+/// in the `sha512-<base64>` format the registry uses
+pub fn sha512_integrity(data: &[u8]) -> String {
+    format!("sha512-{}", base64::encode(Sha512::digest(data)))
+}
+
+fn sha1_integrity(data: &[u8]) -> String {
+    format!("sha1-{}", base64::encode(Sha1::digest(data)))
+}
+
+fn sha1_hex(data: &[u8]) -> String {
+    Sha1::digest(data)
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+/// The SRI string a lockfile should record for `dist`, converting the
+/// hex `shasum` of old packages into an equivalent `sha1-` SRI string
+pub fn dist_integrity(dist: &Dist) -> Option<String> {
+    if dist.integrity.is_some() {
+        return dist.integrity.clone();
+    }
+
+    if dist.shasum.len() != 40 {
+        return None;
+    }
+    let bytes: Option<Vec<u8>> = (0..dist.shasum.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&dist.shasum[i..i + 2], 16).ok())
+        .collect();
+    bytes.map(|bytes| format!("sha1-{}", base64::encode(bytes)))
+}
+
+/// Checks a downloaded tarball against its `dist.integrity` SRI hashes,
+/// falling back to the sha1 `dist.shasum` for packages published before
+/// the registry recorded sha512 hashes
+pub fn verify(name: &str, dist: &Dist, data: &[u8]) -> Result<(), Error> {
+    let hashes: Vec<&str> = dist
+        .integrity
+        .as_deref()
+        .map(|integrity| integrity.split_whitespace().collect())
+        .unwrap_or_default();
+
+    // SRI strings can list several hashes, in which case the strongest one wins
+    let (expected, actual) =
+        if let Some(expected) = hashes.iter().find(|h| h.starts_with("sha512-")) {
+            (expected.to_string(), sha512_integrity(data))
+        } else if let Some(expected) = hashes.iter().find(|h| h.starts_with("sha1-")) {
+            (expected.to_string(), sha1_integrity(data))
+        } else {
+            (dist.shasum.to_lowercase(), sha1_hex(data))
+        };
+
+    if expected == actual {
+        Ok(())
+    } else {
+        Err(Error::IntegrityMismatch {
+            name: name.to_string(),
+            expected,
+            actual,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DATA: &[u8] = b"hello world";
+
+    fn dist(integrity: Option<&str>, shasum: &str) -> Dist {
+        Dist {
+            tarball: String::from("https://registry.npmjs.org/hello/-/hello-1.0.0.tgz"),
+            // DevSkim: ignore DS173237
+            shasum: String::from(shasum),
+            integrity: integrity.map(String::from),
+        }
+    }
+
+    #[test]
+    fn sha512_matches() {
+        let integrity = sha512_integrity(DATA);
+        assert_eq!(
+            integrity,
+            "sha512-MJ7MSJwS1utMxA9QyQLytNDtd+5RGnx6m808qG1M2G+YndNbxf9JlnDaNCVbRbDP2DDoH2Bdz33FVC6TrpzXbw=="
+        );
+        assert_eq!(verify("hello", &dist(Some(&integrity), ""), DATA), Ok(()));
+    }
+
+    #[test]
+    fn falls_back_to_shasum() {
+        let shasum = "2aae6c35c94fcfb415dbe95f408b9ce91ee846ed";
+        assert_eq!(verify("hello", &dist(None, shasum), DATA), Ok(()));
+    }
+
+    #[test]
+    fn shasum_as_integrity() {
+        let shasum = "2aae6c35c94fcfb415dbe95f408b9ce91ee846ed";
+        let integrity = dist_integrity(&dist(None, shasum)).unwrap();
+        assert_eq!(integrity, sha1_integrity(DATA));
+        assert_eq!(verify("hello", &dist(Some(&integrity), ""), DATA), Ok(()));
+    }
+
+    #[test]
+    fn mismatch_names_package() {
+        let result = verify("hello", &dist(None, "0000"), DATA);
+        assert_eq!(
+            result,
+            Err(Error::IntegrityMismatch {
+                name: String::from("hello"),
+                expected: String::from("0000"),
+                actual: String::from("2aae6c35c94fcfb415dbe95f408b9ce91ee846ed"),
+            })
+        );
+    }
+}
@@ -0,0 +1,197 @@
+use std::collections::{BTreeMap, VecDeque};
+use std::path::{Path, PathBuf};
+
+use crate::{package_dir, Graph, PackageId};
+
+/// Whether installing another version of `name` into `dir`'s
+/// `node_modules` would change what a package already placed below `dir`
+/// resolves `name` to, because that package finds it through `dir`
+fn shadows(
+    graph: &Graph,
+    placed: &BTreeMap<PathBuf, PackageId>,
+    dir: &Path,
+    name: &str,
+    id: &PackageId,
+) -> bool {
+    let modules = dir.join("node_modules");
+    placed
+        .iter()
+        .filter(|(path, _)| path.starts_with(&modules))
+        .any(|(path, dependent)| {
+            let wanted = graph
+                .node(dependent)
+                .and_then(|node| node.edges.iter().find(|edge| edge.name == name));
+            if wanted.is_none_or(|edge| &edge.to == id) {
+                return false;
+            }
+            // Where it finds `name` now. Dependents that don't have it yet
+            // are placed later, and nest their own copy if they need to
+            let found = path.ancestors().find(|ancestor| {
+                placed.contains_key(&package_dir(&ancestor.join("node_modules"), name))
+            });
+            found.is_some_and(|found| !found.starts_with(&modules))
+        })
+}
+
+/// Decides where every package in `graph` is installed, relative to the
+/// project directory. Packages are hoisted as close to the top-level
+/// `node_modules` as they can go without shadowing a different version
+/// that some package higher up, or already placed further down, resolves
+/// to, so Node's module resolution finds the right version from every
+/// dependent
+pub fn hoist(graph: &Graph) -> BTreeMap<PathBuf, PackageId> {
+    let mut placed: BTreeMap<PathBuf, PackageId> = BTreeMap::new();
+
+    // Each entry holds the install directories from the project down to a
+    // package, whose dependencies still need placing
+    let mut queue: VecDeque<(Vec<PathBuf>, Option<&PackageId>)> = VecDeque::new();
+    queue.push_back((vec![PathBuf::new()], None));
+
+    while let Some((chain, from)) = queue.pop_front() {
+        let edges = match from {
+            Some(id) => match graph.node(id) {
+                Some(node) => &node.edges,
+                None => continue,
+            },
+            None => graph.roots(),
+        };
+
+        for edge in edges {
+            let mut target = None;
+            for (level, dir) in chain.iter().enumerate().rev() {
+                match placed.get(&package_dir(&dir.join("node_modules"), &edge.name)) {
+                    // Already reachable from here
+                    Some(id) if id == &edge.to => {
+                        target = None;
+                        break;
+                    }
+                    // A different version shadows everything above this level
+                    Some(_) => break,
+                    // Packages deeper down find theirs through here
+                    None if level + 1 < chain.len()
+                        && shadows(graph, &placed, &chain[level], &edge.name, &edge.to) =>
+                    {
+                        break
+                    }
+                    None => target = Some(level),
+                }
+            }
+
+            if let Some(level) = target {
+                let dir = package_dir(&chain[level].join("node_modules"), &edge.name);
+                placed.insert(dir.clone(), edge.to.clone());
+
+                let mut chain = chain[..=level].to_vec();
+                chain.push(dir);
+                queue.push_back((chain, Some(&edge.to)));
+            }
+        }
+    }
+
+    placed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{DependencyKind, Dist, Edge, Metadata, Platform};
+
+    fn add(graph: &mut Graph, from: Option<&PackageId>, name: &str, version: &str) -> PackageId {
+        graph.add_node(Metadata {
+            name: name.to_string(),
+            version: version.to_string(),
+            dependencies: None,
+            peer_dependencies: None,
+            peer_dependencies_meta: None,
+            optional_dependencies: None,
+            platform: Platform::default(),
+            dist: Dist {
+                tarball: String::new(),
+                shasum: String::new(),
+                integrity: None,
+            },
+        });
+        let id = PackageId::new(name, version);
+        graph.add_edge(
+            from,
+            Edge {
+                name: name.to_string(),
+                range: version.to_string(),
+                kind: DependencyKind::Prod,
+                to: id.clone(),
+                overridden: None,
+            },
+        );
+        id
+    }
+
+    #[test]
+    fn hoists_and_nests_conflicts() {
+        let mut graph = Graph::new();
+        let a = add(&mut graph, None, "a", "1.0.0");
+        add(&mut graph, None, "c", "1.0.0");
+        let b = add(&mut graph, Some(&a), "b", "1.0.0");
+        add(&mut graph, Some(&b), "c", "2.0.0");
+        add(&mut graph, Some(&b), "a", "1.0.0");
+
+        let layout = hoist(&graph);
+        let expected: BTreeMap<PathBuf, PackageId> = [
+            ("node_modules/a", PackageId::new("a", "1.0.0")),
+            ("node_modules/b", PackageId::new("b", "1.0.0")),
+            (
+                "node_modules/b/node_modules/c",
+                PackageId::new("c", "2.0.0"),
+            ),
+            ("node_modules/c", PackageId::new("c", "1.0.0")),
+        ]
+        .into_iter()
+        .map(|(path, id)| (PathBuf::from(path), id))
+        .collect();
+        assert_eq!(layout, expected);
+    }
+
+    #[test]
+    fn scoped_packages() {
+        let mut graph = Graph::new();
+        add(&mut graph, None, "@types/node", "18.0.0");
+
+        assert_eq!(
+            hoist(&graph).into_keys().collect::<Vec<_>>(),
+            vec![PathBuf::from("node_modules/@types/node")]
+        );
+    }
+
+    #[test]
+    fn doesnt_shadow_packages_hoisted_past_a_level() {
+        let mut graph = Graph::new();
+        let a = add(&mut graph, None, "a", "1.0.0");
+        add(&mut graph, None, "p", "2.0.0");
+        add(&mut graph, None, "q", "2.0.0");
+        let p = add(&mut graph, Some(&a), "p", "1.0.0");
+        let q = add(&mut graph, Some(&a), "q", "1.0.0");
+        add(&mut graph, Some(&p), "y", "1.0.0");
+        add(&mut graph, Some(&q), "y", "2.0.0");
+
+        let layout = hoist(&graph);
+
+        // What Node finds when a package at `from` requires `name`
+        let require = |from: &Path, name: &str| {
+            from.ancestors()
+                .find_map(|dir| layout.get(&package_dir(&dir.join("node_modules"), name)))
+        };
+        for (path, id) in &layout {
+            for edge in &graph.node(id).unwrap().edges {
+                assert_eq!(
+                    require(path, &edge.name),
+                    Some(&edge.to),
+                    "{}",
+                    path.display()
+                );
+            }
+        }
+        assert_eq!(
+            require(Path::new("node_modules/a/node_modules/p"), "y"),
+            Some(&PackageId::new("y", "1.0.0"))
+        );
+    }
+}
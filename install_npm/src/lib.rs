@@ -1,103 +1,535 @@
 use std::collections::HashMap;
 
-use async_recursion::async_recursion;
 use lazy_static::lazy_static;
 use node_semver::{Range, Version};
+use reqwest::header::{
+    ACCEPT, AUTHORIZATION, ETAG, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED,
+};
 use reqwest::{Client, StatusCode};
-use serde::Deserialize;
-use std::fmt::Debug;
+use serde::{Deserialize, Serialize};
+use std::fmt::{self, Debug, Display};
+use std::time::Duration;
+use tokio::sync::Semaphore;
+
+mod cache;
+pub use cache::{Cache, NetworkMode};
+use cache::{CachedPackument, PackumentFormat, Validators};
+
+mod extract;
+pub use extract::{download, extract, install_package, package_dir};
+
+mod git;
+
+mod graph;
+pub use graph::{Dependency, DependencyKind, Edge, Graph, Node, PackageId};
+
+mod import;
+pub use import::{Import, Unmapped};
+
+mod integrity;
+pub use integrity::{dist_integrity, sha512_integrity, verify};
+
+mod layout;
+pub use layout::hoist;
+
+mod local;
+
+mod lockfile;
+pub use lockfile::{LockedEdge, LockedPackage, Lockfile, LOCKFILE_NAME};
+
+mod manifest;
+pub use manifest::Manifest;
+
+mod npmrc;
+pub use npmrc::{Npmrc, DEFAULT_REGISTRY, NPMRC_NAME};
+
+mod pack;
+pub use pack::pack;
+
+mod overrides;
+pub use overrides::Overrides;
+
+mod package_lock;
+pub use package_lock::{PackageLock, PACKAGE_LOCK_NAME};
+
+mod peers;
+pub use peers::{peer_conflicts, PeerConflict};
+
+mod platform;
+pub use platform::{Host, Platform};
+
+mod pnpm_lock;
+pub use pnpm_lock::{import_pnpm_lock, PNPM_LOCK_NAME};
+
+mod registry;
+pub use registry::{escaped_name, packument_url, tarball_url};
+
+mod resolver;
+pub use resolver::{Resolver, DEFAULT_CONCURRENCY};
+
+mod retry;
+pub use retry::RetryPolicy;
+
+mod spec;
+pub use spec::{GitReference, GitSpec, Spec};
+
+mod store;
+pub use store::Store;
+
+mod yarn_lock;
+pub use yarn_lock::{import_yarn_lock, YARN_LOCK_NAME};
 
 #[derive(PartialEq, Debug)]
 pub enum Error {
-    PackageNotFound,
-    NetworkError,
-    InvalidResponse,
+    /// The registry doesn't know a package called `name`
+    PackageNotFound {
+        name: String,
+    },
+    /// The request for `url` failed before the registry responded,
+    /// even after retrying
+    Network {
+        name: String,
+        url: String,
+        cause: String,
+    },
+    /// The registry responded to the request for `url` with an
+    /// unexpected status, even after retrying
+    HttpStatus {
+        name: String,
+        url: String,
+        status: u16,
+    },
+    /// The registry's response for `url` couldn't be understood
+    InvalidResponse {
+        name: String,
+        url: String,
+        cause: String,
+    },
     RangeNotSatisfied,
     InvalidRange,
+    /// `name` has no dist-tag called `tag`, or it points at a version
+    /// the packument doesn't have
+    DistTagNotFound {
+        name: String,
+        tag: String,
+    },
+    /// The spec of the dependency `name` isn't anything npm understands
+    InvalidSpec {
+        name: String,
+        spec: String,
+    },
+    /// `name` comes from somewhere packages can't be installed from yet,
+    /// like a git repository
+    UnsupportedSpec {
+        name: String,
+        kind: String,
+    },
+    InvalidTarball,
+    IoError,
+    IntegrityMismatch {
+        name: String,
+        expected: String,
+        actual: String,
+    },
+    InvalidLockfile,
+    NotCached {
+        name: String,
+    },
+    /// `name` couldn't be fetched from the git repository at `url`
+    Git {
+        name: String,
+        url: String,
+        cause: String,
+    },
+    /// A `file:` or `link:` dependency couldn't be read from `path`
+    Local {
+        name: String,
+        path: String,
+        cause: String,
+    },
+    /// The config file at `path` couldn't be read
+    Config {
+        path: String,
+        cause: String,
+    },
+    /// The entry `key` of package.json's `overrides` or `resolutions`
+    /// can't be applied
+    InvalidOverride {
+        key: String,
+        cause: String,
+    },
+    /// A package reached through both `first` and `second` (the paths from
+    /// a dependency of the project down to it) would have its dependency on
+    /// `name` overridden differently along each, but is only installed once
+    AmbiguousOverride {
+        name: String,
+        first: String,
+        second: String,
+    },
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::PackageNotFound { name } => write!(f, "{name} was not found in the registry"),
+            Error::Network { name, url, cause } => {
+                write!(f, "failed to fetch {name} from {url}: {cause}")
+            }
+            Error::HttpStatus { name, url, status } => {
+                let reason = StatusCode::from_u16(*status)
+                    .ok()
+                    .and_then(|status| status.canonical_reason())
+                    .unwrap_or_default();
+                write!(
+                    f,
+                    "failed to fetch {name} from {url}: the registry responded with {status} {reason}"
+                )
+            }
+            Error::InvalidResponse { name, url, cause } => {
+                write!(
+                    f,
+                    "the registry sent an invalid response for {name} ({url}): {cause}"
+                )
+            }
+            Error::RangeNotSatisfied => write!(f, "no version satisfies the requested range"),
+            Error::InvalidRange => write!(f, "invalid version range"),
+            Error::DistTagNotFound { name, tag } => {
+                write!(f, "{name} has no version tagged {tag}")
+            }
+            Error::InvalidSpec { name, spec } => {
+                write!(f, "{name}@{spec} isn't a valid dependency spec")
+            }
+            Error::UnsupportedSpec { name, kind } => {
+                write!(f, "{name} is a {kind}, which can't be installed yet")
+            }
+            Error::InvalidTarball => write!(f, "the package tarball is invalid"),
+            Error::IoError => write!(f, "failed to write the package to disk"),
+            Error::IntegrityMismatch {
+                name,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "integrity check failed for {name} (expected {expected}, got {actual})"
+            ),
+            Error::InvalidLockfile => write!(f, "the lockfile is invalid"),
+            Error::NotCached { name } => {
+                write!(
+                    f,
+                    "{name} is not in the local cache, which offline installs need"
+                )
+            }
+            Error::Git { name, url, cause } => {
+                write!(f, "failed to fetch {name} from {url}: {cause}")
+            }
+            Error::Local { name, path, cause } => {
+                write!(f, "failed to read {name} from {path}: {cause}")
+            }
+            Error::Config { path, cause } => write!(f, "failed to read {path}: {cause}"),
+            Error::InvalidOverride { key, cause } => {
+                write!(f, "the override of {key} is invalid: {cause}")
+            }
+            Error::AmbiguousOverride {
+                name,
+                first,
+                second,
+            } => write!(
+                f,
+                "{first} and {second} share one copy, but overrides resolve its {name} \
+                 differently"
+            ),
+        }
+    }
 }
 
+impl std::error::Error for Error {}
+
 lazy_static! {
-    static ref CLIENT: Client = Client::new();
+    /// Timeouts match npm's, and are retried like any other dropped connection
+    pub(crate) static ref CLIENT: Client = Client::builder()
+        .connect_timeout(Duration::from_secs(30))
+        .timeout(Duration::from_secs(300))
+        .build()
+        .unwrap();
 }
 
 #[derive(Deserialize)]
 struct RawMetadata {
     versions: HashMap<Version, Metadata>,
+    #[serde(rename = "dist-tags", default)]
+    dist_tags: HashMap<String, String>,
+    /// When each version was published, only in the full document. Not
+    /// every value is a date, like the `unpublished` of a removed package
+    #[serde(default)]
+    time: HashMap<String, serde_json::Value>,
 }
 
 #[derive(Deserialize, PartialEq, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
 pub struct Metadata {
     pub name: String,
     pub version: String,
     pub dependencies: Option<HashMap<String, String>>,
+    /// Packages the dependent has to provide, like the `react` of a React
+    /// plugin
+    pub peer_dependencies: Option<HashMap<String, String>>,
+    pub peer_dependencies_meta: Option<HashMap<String, PeerDependencyMeta>>,
+    /// Also listed in `dependencies`, but allowed to fail
+    pub optional_dependencies: Option<HashMap<String, String>>,
+    #[serde(flatten)]
+    pub platform: Platform,
     pub dist: Dist,
 }
 
+impl Metadata {
+    /// Whether the peer dependency called `name` may be left out
+    pub fn is_optional_peer(&self, name: &str) -> bool {
+        self.peer_dependencies_meta
+            .as_ref()
+            .and_then(|meta| meta.get(name))
+            .is_some_and(|meta| meta.optional)
+    }
+}
+
+/// What `peerDependenciesMeta` says about a peer dependency
+#[derive(Serialize, Deserialize, PartialEq, Eq, Debug, Clone, Default)]
+pub struct PeerDependencyMeta {
+    /// Optional peers are only linked when something else installs them
+    #[serde(default)]
+    pub optional: bool,
+}
+
 #[derive(Deserialize, PartialEq, Debug, Clone)]
 pub struct Dist {
     pub tarball: String,
     pub shasum: String,
+    pub integrity: Option<String>,
 }
 
-pub async fn get_metadata(name: String, range: Range, registry: &str) -> Result<Metadata, Error> {
-    let response = match CLIENT.get(format!("{registry}/{name}")).send().await {
-        Ok(response) => response,
-        Err(e) => {
-            if e.status() == Some(StatusCode::NOT_FOUND) {
-                return Err(Error::PackageNotFound);
-            } else {
-                return Err(Error::NetworkError);
+/// Fetches the packument of `name`, in `format`. With a [Cache], a cached
+/// packument is revalidated instead of downloaded again when the registry
+/// supports it, or used without asking the registry at all, depending on
+/// `mode`. With a `limit`, a permit is held while a request is in flight
+async fn fetch_packument(
+    name: &str,
+    npmrc: &Npmrc,
+    format: PackumentFormat,
+    cache: Option<&Cache>,
+    mode: NetworkMode,
+    retry: &RetryPolicy,
+    limit: Option<&Semaphore>,
+) -> Result<RawMetadata, Error> {
+    let registry = npmrc.registry_for(name);
+    let url = packument_url(registry, name);
+    let authorization = npmrc.authorization(name, &url);
+    let parse = |body: &str| {
+        serde_json::from_str(body).map_err(|e| Error::InvalidResponse {
+            name: name.to_string(),
+            url: url.clone(),
+            cause: e.to_string(),
+        })
+    };
+
+    let cached = cache.and_then(|cache| cache.read(registry, name, format));
+    let cached = match (mode, cached) {
+        (NetworkMode::Online, cached) => cached,
+        (_, Some(cached)) => return parse(&cached.body),
+        (NetworkMode::Offline, None) => {
+            return Err(Error::NotCached {
+                name: name.to_string(),
+            })
+        }
+        (NetworkMode::PreferOffline, None) => None,
+    };
+
+    let request = || {
+        let mut request = CLIENT.get(&url).header(ACCEPT, format.accept());
+        if let Some(authorization) = &authorization {
+            request = request.header(AUTHORIZATION, authorization);
+        }
+        if let Some(cached) = &cached {
+            if let Some(etag) = &cached.validators.etag {
+                request = request.header(IF_NONE_MATCH, etag);
+            }
+            if let Some(last_modified) = &cached.validators.last_modified {
+                request = request.header(IF_MODIFIED_SINCE, last_modified);
             }
         }
-    }
-    .json::<RawMetadata>()
-    .await;
+        request
+    };
+    let (response, _permit) = retry::send(request, name, &url, retry, limit).await?;
+
+    let body = match (response.status(), cached) {
+        (StatusCode::NOT_MODIFIED, Some(cached)) => cached.body,
+        (StatusCode::NOT_FOUND, _) => {
+            return Err(Error::PackageNotFound {
+                name: name.to_string(),
+            })
+        }
+        (status, _) if status.is_success() => {
+            let header = |name| {
+                response
+                    .headers()
+                    .get(name)
+                    .and_then(|value| value.to_str().ok())
+                    .map(String::from)
+            };
+            let validators = Validators {
+                etag: header(ETAG),
+                last_modified: header(LAST_MODIFIED),
+            };
+            let body = response.text().await.map_err(|e| Error::Network {
+                name: name.to_string(),
+                url: url.clone(),
+                cause: retry::cause(&e),
+            })?;
 
-    let response = match response {
-        Ok(r) => r,
-        _ => return Err(Error::InvalidResponse),
+            if let Some(cache) = cache {
+                // A cache that can't be written to only costs a download
+                let _ = cache.write(
+                    registry,
+                    name,
+                    format,
+                    &CachedPackument {
+                        validators,
+                        body: body.clone(),
+                    },
+                );
+            }
+            body
+        }
+        (status, _) => {
+            return Err(Error::HttpStatus {
+                name: name.to_string(),
+                url,
+                status: status.as_u16(),
+            })
+        }
     };
 
-    for (key, value) in response.versions {
-        if range.satisfies(&key) {
-            return Ok(value);
+    parse(&body)
+}
+
+fn resolve(packument: &RawMetadata, range: &Range) -> Result<Metadata, Error> {
+    match select_version(&packument.versions, &packument.dist_tags, range) {
+        Some(version) => Ok(packument.versions[version].clone()),
+        None => Err(Error::RangeNotSatisfied),
+    }
+}
+
+/// What a registry dependency asks for: versions satisfying a range, or
+/// the version a dist-tag (like `latest` or `next`) points at
+#[derive(PartialEq, Debug, Clone)]
+pub enum VersionSpec {
+    Range(Range),
+    Tag(String),
+}
+
+impl VersionSpec {
+    /// Like npm, anything that isn't a valid range is a tag, as long as it
+    /// could appear in a URL as is
+    pub fn parse(spec: &str) -> Result<Self, Error> {
+        if let Ok(range) = Range::parse(spec) {
+            return Ok(VersionSpec::Range(range));
         }
+
+        let is_tag = !spec.is_empty()
+            && spec
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || "-_.!~*'()".contains(c));
+        if is_tag {
+            Ok(VersionSpec::Tag(spec.to_string()))
+        } else {
+            Err(Error::InvalidRange)
+        }
+    }
+}
+
+/// Resolves `spec` against the packument of `name`
+fn resolve_spec(
+    name: &str,
+    packument: &RawMetadata,
+    spec: &VersionSpec,
+) -> Result<Metadata, Error> {
+    match spec {
+        VersionSpec::Range(range) => resolve(packument, range),
+        VersionSpec::Tag(tag) => packument
+            .dist_tags
+            .get(tag)
+            .and_then(|version| Version::parse(version).ok())
+            .and_then(|version| packument.versions.get(&version))
+            .cloned()
+            .ok_or_else(|| Error::DistTagNotFound {
+                name: name.to_string(),
+                tag: tag.clone(),
+            }),
     }
+}
 
-    Err(Error::RangeNotSatisfied)
+pub async fn get_metadata(name: String, range: Range, registry: &str) -> Result<Metadata, Error> {
+    let packument = fetch_packument(
+        &name,
+        &Npmrc::new(registry),
+        PackumentFormat::Abbreviated,
+        None,
+        NetworkMode::Online,
+        &RetryPolicy::default(),
+        None,
+    )
+    .await?;
+    resolve(&packument, &range)
 }
 
-/// **note:** this function does NOT dedup the result
-/// (users should merge together the resulting Vecs and dedup then)
-#[async_recursion]
-pub async fn walk_dependencies(
-    name: &String,
-    range: Range,
-    registry: &str,
-) -> Result<Vec<Metadata>, Error> {
-    let mut result: Vec<Metadata> = Vec::with_capacity(256);
+/// Whether `version` is allowed to satisfy `range`. Like npm, a prerelease
+/// only matches if the range mentions a prerelease of the same `major.minor.patch`
+fn allows_prerelease(range: &Range, version: &Version) -> bool {
+    if !version.is_prerelease() {
+        return true;
+    }
+
+    range
+        .to_string()
+        .split(|c: char| c == '|' || c.is_whitespace())
+        .filter_map(|comparator| {
+            Version::parse(comparator.trim_start_matches(|c| "<>=~^v".contains(c))).ok()
+        })
+        .any(|bound| {
+            bound.is_prerelease()
+                && (bound.major, bound.minor, bound.patch)
+                    == (version.major, version.minor, version.patch)
+        })
+}
 
-    let metadata = get_metadata(name.to_string(), range, registry).await?;
-    let deps = metadata.dependencies.clone();
-    result.push(metadata);
+/// Picks the version to install for `range`, the same way npm does:
+/// the `latest` dist-tag if it satisfies the range, otherwise the highest
+/// satisfying version
+fn select_version<'a, T>(
+    versions: &'a HashMap<Version, T>,
+    dist_tags: &HashMap<String, String>,
+    range: &Range,
+) -> Option<&'a Version> {
+    let satisfies =
+        |version: &Version| range.satisfies(version) && allows_prerelease(range, version);
 
-    if let Some(deps) = deps {
-        for (key, value) in deps {
-            let range = match Range::parse(value) {
-                Ok(r) => r,
-                Err(_) => return Err(Error::InvalidRange),
-            };
-            let mut walked = walk_dependencies(&key, range, registry).await?;
-            result.append(&mut walked);
-        }
+    let latest = dist_tags
+        .get("latest")
+        .and_then(|latest| Version::parse(latest).ok())
+        .and_then(|latest| versions.get_key_value(&latest))
+        .map(|(version, _)| version)
+        .filter(|version| satisfies(version));
+    if latest.is_some() {
+        return latest;
     }
 
-    Ok(result)
+    versions.keys().filter(|version| satisfies(version)).max()
 }
 
 #[cfg(test)]
-mod tests {
+pub(crate) mod tests {
     use super::*;
+    use std::io::{Read, Write};
+    use std::net::TcpListener;
+    use std::thread::{self, JoinHandle};
 
     const REGISTRY: &str = "https://registry.npmjs.org";
 
@@ -122,6 +554,311 @@ mod tests {
         };
     }
 
+    fn versions(list: &[&str]) -> HashMap<Version, ()> {
+        list.iter()
+            .map(|v| (Version::parse(v).unwrap(), ()))
+            .collect()
+    }
+
+    fn tags(latest: &str) -> HashMap<String, String> {
+        HashMap::from([(s!("latest"), s!(latest))])
+    }
+
+    #[test]
+    fn selects_highest_satisfying() {
+        let versions = versions(&["4.0.0", "4.18.1", "4.2.0", "5.0.0"]);
+        let range = Range::parse("^4.0.0").unwrap();
+        assert_eq!(
+            select_version(&versions, &HashMap::new(), &range),
+            Some(&Version::parse("4.18.1").unwrap())
+        );
+    }
+
+    #[test]
+    fn prefers_latest_tag() {
+        let versions = versions(&["4.17.3", "4.18.1", "5.0.0"]);
+        let range = Range::parse("^4.0.0").unwrap();
+        assert_eq!(
+            select_version(&versions, &tags("4.17.3"), &range),
+            Some(&Version::parse("4.17.3").unwrap())
+        );
+        assert_eq!(
+            select_version(&versions, &tags("5.0.0"), &range),
+            Some(&Version::parse("4.18.1").unwrap())
+        );
+    }
+
+    #[test]
+    fn skips_prereleases() {
+        let versions = versions(&["1.1.0", "1.2.0-beta.1", "2.0.0-rc.1"]);
+        assert_eq!(
+            select_version(&versions, &HashMap::new(), &Range::parse("*").unwrap()),
+            Some(&Version::parse("1.1.0").unwrap())
+        );
+        assert_eq!(
+            select_version(
+                &versions,
+                &HashMap::new(),
+                &Range::parse("^1.2.0-beta.0").unwrap()
+            ),
+            Some(&Version::parse("1.2.0-beta.1").unwrap())
+        );
+        assert_eq!(
+            select_version(&versions, &tags("2.0.0-rc.1"), &Range::parse("*").unwrap()),
+            Some(&Version::parse("1.1.0").unwrap())
+        );
+    }
+
+    #[test]
+    fn parses_tags() {
+        assert_eq!(
+            VersionSpec::parse("^1.0.0"),
+            Ok(VersionSpec::Range(Range::parse("^1.0.0").unwrap()))
+        );
+        assert_eq!(VersionSpec::parse("next"), Ok(VersionSpec::Tag(s!("next"))));
+        assert_eq!(
+            VersionSpec::parse("beta-2.x"),
+            Ok(VersionSpec::Tag(s!("beta-2.x")))
+        );
+        assert_eq!(VersionSpec::parse("not a tag"), Err(Error::InvalidRange));
+    }
+
+    #[test]
+    fn resolves_tags() {
+        let mut packument = packument(vec![
+            metadata("a", "1.0.0", &[]),
+            metadata("a", "2.0.0-rc.1", &[]),
+        ]);
+        packument.dist_tags = HashMap::from([
+            (s!("latest"), s!("1.0.0")),
+            (s!("next"), s!("2.0.0-rc.1")),
+            (s!("old"), s!("0.1.0")),
+        ]);
+        let version = |spec: &str| {
+            resolve_spec("a", &packument, &VersionSpec::parse(spec).unwrap())
+                .map(|metadata| metadata.version)
+        };
+
+        assert_eq!(version("next"), Ok(s!("2.0.0-rc.1")));
+        assert_eq!(version("latest"), Ok(s!("1.0.0")));
+        for tag in ["old", "missing"] {
+            assert_eq!(
+                version(tag),
+                Err(Error::DistTagNotFound {
+                    name: s!("a"),
+                    tag: s!(tag)
+                })
+            );
+        }
+    }
+
+    pub(crate) fn metadata(name: &str, version: &str, dependencies: &[(&str, &str)]) -> Metadata {
+        Metadata {
+            name: s!(name),
+            version: s!(version),
+            dependencies: Some(
+                dependencies
+                    .iter()
+                    .map(|(name, range)| (s!(*name), s!(*range)))
+                    .collect(),
+            ),
+            peer_dependencies: None,
+            peer_dependencies_meta: None,
+            optional_dependencies: None,
+            platform: Platform::default(),
+            dist: Dist {
+                tarball: tarball_url(REGISTRY, name, version),
+                shasum: s!(""),
+                integrity: None,
+            },
+        }
+    }
+
+    pub(crate) fn packument(versions: Vec<Metadata>) -> RawMetadata {
+        RawMetadata {
+            versions: versions
+                .into_iter()
+                .map(|metadata| (Version::parse(&metadata.version).unwrap(), metadata))
+                .collect(),
+            dist_tags: HashMap::new(),
+            time: HashMap::new(),
+        }
+    }
+
+    /// An HTTP/1.1 response closing its connection, so every request
+    /// a test makes reaches [serve] separately
+    pub(crate) fn response(status: &str, headers: &[(&str, &str)], body: &str) -> String {
+        let headers: String = headers
+            .iter()
+            .map(|(name, value)| format!("{name}: {value}\r\n"))
+            .collect();
+        format!(
+            "HTTP/1.1 {status}\r\ncontent-length: {}\r\nconnection: close\r\n{headers}\r\n{body}",
+            body.len()
+        )
+    }
+
+    /// A 200 response with a binary body, like a tarball
+    pub(crate) fn binary_response(body: &[u8]) -> Vec<u8> {
+        let mut response = format!(
+            "HTTP/1.1 200 OK\r\ncontent-length: {}\r\nconnection: close\r\n\r\n",
+            body.len()
+        )
+        .into_bytes();
+        response.extend_from_slice(body);
+        response
+    }
+
+    /// Answers requests on a local port with `responses`, in order.
+    /// Returns the port's URL and a handle to the requests it received
+    pub(crate) fn serve<R: AsRef<[u8]> + Send + 'static>(
+        responses: Vec<R>,
+    ) -> (String, JoinHandle<Vec<String>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+
+        let handle = thread::spawn(move || {
+            responses
+                .into_iter()
+                .map(|response| {
+                    let (mut stream, _) = listener.accept().unwrap();
+                    let mut request = Vec::new();
+                    let mut buffer = [0; 1024];
+                    while !request.windows(4).any(|window| window == b"\r\n\r\n") {
+                        let read = stream.read(&mut buffer).unwrap();
+                        if read == 0 {
+                            break;
+                        }
+                        request.extend_from_slice(&buffer[..read]);
+                    }
+                    stream.write_all(response.as_ref()).unwrap();
+                    String::from_utf8_lossy(&request).to_lowercase()
+                })
+                .collect()
+        });
+
+        (url, handle)
+    }
+
+    #[test]
+    fn revalidates_cached_packuments() {
+        let body = r#"{"versions":{"1.0.0":{"name":"a","version":"1.0.0","dist":{"tarball":"","shasum":""}}}}"#;
+        let (url, requests) = serve(vec![
+            response("200 OK", &[("etag", "\"v1\"")], body),
+            response("304 Not Modified", &[], ""),
+        ]);
+        let dir = tempdir::TempDir::new("vortex-cache").unwrap();
+        let cache = Cache::new(dir.path());
+
+        for _ in 0..2 {
+            let packument = aw!(fetch_packument(
+                "a",
+                &Npmrc::new(&url),
+                PackumentFormat::Abbreviated,
+                Some(&cache),
+                NetworkMode::Online,
+                &RetryPolicy::none(),
+                None
+            ))
+            .unwrap();
+            assert_eq!(packument.versions.len(), 1);
+        }
+
+        let requests = requests.join().unwrap();
+        assert!(requests[0].contains("accept: application/vnd.npm.install-v1+json"));
+        assert!(!requests[0].contains("if-none-match"));
+        assert!(requests[1].contains("if-none-match: \"v1\""));
+    }
+
+    #[test]
+    fn offline_only_reads_the_cache() {
+        // Nothing listens on the discard port, so any request would fail
+        let registry = "http://127.0.0.1:9";
+        let dir = tempdir::TempDir::new("vortex-cache").unwrap();
+        let cache = Cache::new(dir.path());
+        let fetch = |mode| {
+            aw!(fetch_packument(
+                "a",
+                &Npmrc::new(registry),
+                PackumentFormat::Abbreviated,
+                Some(&cache),
+                mode,
+                &RetryPolicy::none(),
+                None
+            ))
+        };
+
+        assert_eq!(
+            fetch(NetworkMode::Offline).err(),
+            Some(Error::NotCached { name: s!("a") })
+        );
+        assert!(matches!(
+            fetch(NetworkMode::PreferOffline),
+            Err(Error::Network { .. })
+        ));
+
+        cache
+            .write(
+                registry,
+                "a",
+                PackumentFormat::Abbreviated,
+                &CachedPackument {
+                    validators: Validators::default(),
+                    body: s!(r#"{"versions":{}}"#),
+                },
+            )
+            .unwrap();
+        assert!(fetch(NetworkMode::Offline).is_ok());
+        assert!(fetch(NetworkMode::PreferOffline).is_ok());
+        assert!(matches!(
+            fetch(NetworkMode::Online),
+            Err(Error::Network { .. })
+        ));
+    }
+
+    #[test]
+    fn sends_registry_credentials() {
+        let (url, requests) = serve(vec![response("200 OK", &[], r#"{"versions":{}}"#)]);
+        let dart = url.trim_start_matches("http:");
+        let npmrc = Npmrc::parsed(&format!("@scope:registry={url}\n{dart}/:_authToken=secret"));
+
+        aw!(fetch_packument(
+            "@scope/a",
+            &npmrc,
+            PackumentFormat::Abbreviated,
+            None,
+            NetworkMode::Online,
+            &RetryPolicy::none(),
+            None
+        ))
+        .unwrap();
+
+        assert!(requests.join().unwrap()[0].contains("authorization: bearer secret"));
+    }
+
+    #[test]
+    fn reports_missing_packages() {
+        let (url, _) = serve(vec![response(
+            "404 Not Found",
+            &[],
+            "{\"error\":\"Not found\"}",
+        )]);
+
+        assert_eq!(
+            aw!(fetch_packument(
+                "a",
+                &Npmrc::new(&url),
+                PackumentFormat::Abbreviated,
+                None,
+                NetworkMode::Online,
+                &RetryPolicy::none(),
+                None
+            ))
+            .err(),
+            Some(Error::PackageNotFound { name: s!("a") })
+        );
+    }
+
     #[test]
     fn valid_lodash() {
         let metadata = aw!(get_metadata(
@@ -135,10 +872,15 @@ mod tests {
                 name: s!("lodash"),
                 version: s!("1.2.1"),
                 dependencies: None,
+                peer_dependencies: None,
+                peer_dependencies_meta: None,
+                optional_dependencies: None,
+                platform: Platform::default(),
                 dist: Dist {
                     // Tell DevSkim that a SHA-1 hash, funnily enough, is not a token
                     // DevSkim: ignore DS173237
                     shasum: s!("ed47b16e46f06b2b40309b68e9163c17e93ea304"),
+                    integrity: None,
                     tarball: s!("https://registry.npmjs.org/lodash/-/lodash-1.2.1.tgz")
                 }
             }
@@ -0,0 +1,264 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Component, Path, PathBuf};
+
+use crate::extract::files;
+use crate::{pack, sha512_integrity, Dist, Error, Manifest, Metadata, Spec};
+
+/// A package installed from disk instead of a registry
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub(crate) enum Local {
+    /// Packed and copied into `node_modules`
+    Directory(PathBuf),
+    /// Extracted into `node_modules`
+    Tarball(PathBuf),
+    /// Symlinked into `node_modules` as is
+    Link(PathBuf),
+}
+
+fn error(name: &str, path: &Path, cause: impl Into<String>) -> Error {
+    Error::Local {
+        name: name.to_string(),
+        path: path.display().to_string(),
+        cause: cause.into(),
+    }
+}
+
+/// `path` with `/` separators, the way it is written in package.json and
+/// lockfiles on every platform
+fn slashed(path: &Path) -> String {
+    path.components()
+        .map(|part| part.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+impl Local {
+    /// The local package `spec` points at, if it points at one
+    pub(crate) fn from_spec(spec: Spec) -> Option<Self> {
+        match spec {
+            Spec::Directory(path) => Some(Local::Directory(path)),
+            Spec::LocalTarball(path) => Some(Local::Tarball(path)),
+            Spec::Link(path) => Some(Local::Link(path)),
+            _ => None,
+        }
+    }
+
+    fn path(&self) -> &Path {
+        match self {
+            Local::Directory(path) | Local::Tarball(path) | Local::Link(path) => path,
+        }
+    }
+
+    /// How the package is recorded as resolved, like npm does:
+    /// `file:../shared` or `link:../shared`
+    fn resolved(&self) -> String {
+        match self {
+            Local::Link(path) => format!("link:{}", slashed(path)),
+            _ => format!("file:{}", slashed(self.path())),
+        }
+    }
+
+    /// The tarball the package is installed from, packing it first if it
+    /// is a directory. Links aren't installed from a tarball
+    pub(crate) fn tarball(&self, name: &str) -> Result<Vec<u8>, Error> {
+        match self {
+            Local::Directory(path) => pack(path),
+            Local::Tarball(path) => fs::read(path).map_err(|e| error(name, path, e.to_string())),
+            Local::Link(path) => Err(error(name, path, "linked packages aren't packed")),
+        }
+    }
+
+    /// Reads the metadata of the package called `name`, as if a registry
+    /// had published it. Local specs among its dependencies are relative to
+    /// the package, so they are rewritten to be relative to the project
+    pub(crate) fn metadata(&self, name: &str) -> Result<Metadata, Error> {
+        let path = self.path();
+        let (manifest, integrity): (Option<Manifest>, _) = match self {
+            Local::Tarball(_) => {
+                let tarball = self.tarball(name)?;
+                let manifest = files(&tarball)?
+                    .into_iter()
+                    .find(|file| file.path == Path::new("package.json"))
+                    .and_then(|file| serde_json::from_slice(&file.contents).ok());
+                (manifest, Some(sha512_integrity(&tarball)))
+            }
+            _ => match fs::read_to_string(path.join("package.json")) {
+                Ok(contents) => (serde_json::from_str(&contents).ok(), None),
+                Err(e) if e.kind() == ErrorKind::NotFound && path.is_dir() => (None, None),
+                Err(e) => return Err(error(name, path, e.to_string())),
+            },
+        };
+        let manifest = match (manifest, self) {
+            (Some(manifest), _) => manifest,
+            // Anything can be linked, but only packages can be installed
+            (None, Local::Link(_)) => Manifest::default(),
+            (None, _) => return Err(error(name, path, "there is no valid package.json")),
+        };
+
+        let mut metadata = manifest.into_metadata(
+            name,
+            Dist {
+                tarball: self.resolved(),
+                shasum: String::new(),
+                integrity,
+            },
+        );
+        match self {
+            // A linked package brings its own node_modules
+            Local::Link(_) => {
+                metadata.dependencies = Some(HashMap::new());
+                metadata.peer_dependencies = None;
+            }
+            Local::Directory(dir) => {
+                for (dependency, spec) in metadata.dependencies.iter_mut().flatten() {
+                    if let Ok(Some(local)) = Spec::parse(dependency, spec).map(Local::from_spec) {
+                        *spec = local.relative_to(dir).resolved();
+                    }
+                }
+            }
+            Local::Tarball(_) => {}
+        }
+        Ok(metadata)
+    }
+
+    /// The same package, with its path resolved against `dir`
+    fn relative_to(&self, dir: &Path) -> Self {
+        let path = dir.join(self.path());
+        let mut normalized = PathBuf::new();
+        for component in path.components() {
+            match component {
+                Component::CurDir => {}
+                Component::ParentDir
+                    if matches!(
+                        normalized.components().next_back(),
+                        Some(Component::Normal(_))
+                    ) =>
+                {
+                    normalized.pop();
+                }
+                component => normalized.push(component),
+            }
+        }
+
+        match self {
+            Local::Directory(_) => Local::Directory(normalized),
+            Local::Tarball(_) => Local::Tarball(normalized),
+            Local::Link(_) => Local::Link(normalized),
+        }
+    }
+}
+
+/// The local package a package was resolved from, if it was
+pub(crate) fn parse_resolved(name: &str, resolved: &str) -> Option<Local> {
+    Spec::parse(name, resolved).ok().and_then(Local::from_spec)
+}
+
+/// The path from `from` to `to`, both absolute
+fn relative(from: &Path, to: &Path) -> PathBuf {
+    let from: Vec<_> = from.components().collect();
+    let to: Vec<_> = to.components().collect();
+    let common = from.iter().zip(&to).take_while(|(a, b)| a == b).count();
+
+    let mut path: PathBuf = from[common..]
+        .iter()
+        .map(|_| Component::ParentDir)
+        .collect();
+    path.extend(&to[common..]);
+    path
+}
+
+/// Symlinks `destination` to the directory at `target`, replacing whatever
+/// was there. The link is relative, so the project can be moved
+pub(crate) fn link(name: &str, target: &Path, destination: &Path) -> Result<(), Error> {
+    let io_error = |e: std::io::Error| error(name, target, e.to_string());
+    let target = fs::canonicalize(target).map_err(io_error)?;
+    let parent = destination.parent().ok_or(Error::IoError)?;
+    fs::create_dir_all(parent).map_err(|_| Error::IoError)?;
+    let parent = fs::canonicalize(parent).map_err(|_| Error::IoError)?;
+
+    match fs::symlink_metadata(destination) {
+        Ok(metadata) if metadata.is_dir() => fs::remove_dir_all(destination),
+        Ok(_) => fs::remove_file(destination),
+        Err(_) => Ok(()),
+    }
+    .map_err(|_| Error::IoError)?;
+
+    let link = relative(&parent, &target);
+    #[cfg(unix)]
+    let linked = std::os::unix::fs::symlink(&link, destination);
+    #[cfg(windows)]
+    let linked = std::os::windows::fs::symlink_dir(&link, destination);
+    linked.map_err(io_error)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::extract::tests::tarball;
+    use tempdir::TempDir;
+
+    #[test]
+    fn reads_directories() {
+        let dir = TempDir::new("vortex-local").unwrap();
+        let shared = dir.path().join("shared");
+        fs::create_dir_all(&shared).unwrap();
+        fs::write(
+            shared.join("package.json"),
+            r#"{"name":"shared","version":"1.0.0","dependencies":{"a":"^1.0.0","utils":"file:../utils"}}"#,
+        )
+        .unwrap();
+
+        let metadata = Local::Directory(shared).metadata("s").unwrap();
+
+        assert_eq!(metadata.name, "shared");
+        assert_eq!(metadata.dist.integrity, None);
+        let dependencies = metadata.dependencies.unwrap();
+        assert_eq!(dependencies["a"], "^1.0.0");
+        assert_eq!(
+            dependencies["utils"],
+            format!("file:{}", slashed(&dir.path().join("utils")))
+        );
+    }
+
+    #[test]
+    fn reads_tarballs() {
+        let dir = TempDir::new("vortex-local").unwrap();
+        let path = dir.path().join("a-1.0.0.tgz");
+        let contents = tarball(&[("package/package.json", r#"{"name":"a","version":"1.0.0"}"#)]);
+        fs::write(&path, &contents).unwrap();
+
+        let metadata = Local::Tarball(path.clone()).metadata("a").unwrap();
+
+        assert_eq!(metadata.version, "1.0.0");
+        assert_eq!(metadata.dist.tarball, format!("file:{}", slashed(&path)));
+        assert_eq!(metadata.dist.integrity, Some(sha512_integrity(&contents)));
+        assert_eq!(
+            parse_resolved("a", &metadata.dist.tarball),
+            Some(Local::Tarball(path))
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn links_relatively() {
+        let dir = TempDir::new("vortex-local").unwrap();
+        let shared = dir.path().join("shared");
+        fs::create_dir_all(&shared).unwrap();
+        fs::write(shared.join("index.js"), "1").unwrap();
+        let destination = dir.path().join("app").join("node_modules").join("shared");
+
+        link("shared", &shared, &destination).unwrap();
+        link("shared", &shared, &destination).unwrap();
+
+        assert_eq!(
+            fs::read_link(&destination).unwrap(),
+            Path::new("../../shared")
+        );
+        assert_eq!(
+            fs::read_to_string(destination.join("index.js")).unwrap(),
+            "1"
+        );
+    }
+}
@@ -0,0 +1,581 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs;
+use std::io::ErrorKind;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::package_lock::Flags;
+use crate::{
+    dist_integrity, git, Dependency, DependencyKind, Dist, Edge, Error, Graph, Metadata, Overrides,
+    PackageId, PeerDependencyMeta, Platform,
+};
+
+/// The file vortex keeps its lockfile in, next to package.json
+pub const LOCKFILE_NAME: &str = "vortex-lock.json";
+
+const LOCKFILE_VERSION: u32 = 1;
+
+fn is_prod(kind: &DependencyKind) -> bool {
+    *kind == DependencyKind::Prod
+}
+
+fn is_false(value: &bool) -> bool {
+    !value
+}
+
+/// A resolved dependency edge
+#[derive(Serialize, Deserialize, PartialEq, Eq, Debug, Clone)]
+pub struct LockedEdge {
+    pub range: String,
+    /// The package the edge resolved to, when it isn't named after the
+    /// dependency (like git dependencies and aliases)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    pub version: String,
+    #[serde(default, skip_serializing_if = "is_prod")]
+    pub kind: DependencyKind,
+    /// The spec an override resolved the edge with instead of `range`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub overridden: Option<String>,
+}
+
+/// Everything needed to install a package without asking the registry
+#[derive(Serialize, Deserialize, PartialEq, Eq, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct LockedPackage {
+    pub name: String,
+    pub version: String,
+    pub resolved: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub integrity: Option<String>,
+    /// Kept so packages for other platforms can be skipped when installing
+    #[serde(flatten)]
+    pub platform: Platform,
+    /// Only needed for development, like npm's flag of the same name
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub dev: bool,
+    /// Only needed by optional dependencies
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub optional: bool,
+    /// Needed by both, so only left out when both are omitted
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub dev_optional: bool,
+    /// Only needed as a peer dependency
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub peer: bool,
+    /// Kept so optional peers stay optional when the graph is rebuilt
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub peer_dependencies_meta: BTreeMap<String, PeerDependencyMeta>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub dependencies: BTreeMap<String, LockedEdge>,
+}
+
+impl LockedPackage {
+    fn edges(&self, include: impl Fn(DependencyKind) -> bool) -> HashMap<String, String> {
+        self.dependencies
+            .iter()
+            .filter(|(_, edge)| include(edge.kind))
+            .map(|(name, edge)| (name.clone(), edge.range.clone()))
+            .collect()
+    }
+
+    /// The registry metadata this package was locked from
+    pub fn metadata(&self) -> Metadata {
+        Metadata {
+            name: self.name.clone(),
+            version: self.version.clone(),
+            dependencies: Some(self.edges(|kind| kind != DependencyKind::Peer)),
+            peer_dependencies: Some(self.edges(|kind| kind == DependencyKind::Peer)),
+            peer_dependencies_meta: Some(
+                self.peer_dependencies_meta
+                    .iter()
+                    .map(|(name, meta)| (name.clone(), meta.clone()))
+                    .collect(),
+            ),
+            optional_dependencies: Some(self.edges(|kind| kind == DependencyKind::Optional)),
+            platform: self.platform.clone(),
+            dist: Dist {
+                tarball: self.resolved.clone(),
+                shasum: String::new(),
+                integrity: self.integrity.clone(),
+            },
+        }
+    }
+}
+
+/// `vortex-lock.json`: every package of a resolved [Graph], keyed by
+/// `name@version`, along with the project's own dependencies. Maps are
+/// sorted, so the same graph always produces the same file
+#[derive(Serialize, Deserialize, PartialEq, Eq, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct Lockfile {
+    pub lockfile_version: u32,
+    /// The overrides the packages were resolved with, written like Yarn
+    /// resolutions
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub overrides: BTreeMap<String, String>,
+    pub dependencies: BTreeMap<String, LockedEdge>,
+    pub packages: BTreeMap<String, LockedPackage>,
+}
+
+fn lock_edges(edges: &[Edge]) -> BTreeMap<String, LockedEdge> {
+    edges
+        .iter()
+        .map(|edge| {
+            (
+                edge.name.clone(),
+                LockedEdge {
+                    range: edge.range.clone(),
+                    name: (edge.to.name != edge.name).then(|| edge.to.name.clone()),
+                    version: edge.to.version.clone(),
+                    kind: edge.kind,
+                    overridden: edge.overridden.clone(),
+                },
+            )
+        })
+        .collect()
+}
+
+impl Lockfile {
+    pub fn from_graph(graph: &Graph) -> Self {
+        let flags = Flags::of(graph);
+        Self {
+            lockfile_version: LOCKFILE_VERSION,
+            overrides: BTreeMap::new(),
+            dependencies: lock_edges(graph.roots()),
+            packages: graph
+                .nodes()
+                .map(|(id, node)| {
+                    (
+                        id.to_string(),
+                        LockedPackage {
+                            name: id.name.clone(),
+                            version: id.version.clone(),
+                            resolved: node.metadata.dist.tarball.clone(),
+                            integrity: dist_integrity(&node.metadata.dist),
+                            platform: node.metadata.platform.clone(),
+                            dev: flags[id].dev,
+                            optional: flags[id].optional,
+                            dev_optional: flags[id].dev_optional,
+                            peer: flags[id].peer,
+                            peer_dependencies_meta: node
+                                .metadata
+                                .peer_dependencies_meta
+                                .iter()
+                                .flatten()
+                                .map(|(name, meta)| (name.clone(), meta.clone()))
+                                .collect(),
+                            dependencies: lock_edges(&node.edges),
+                        },
+                    )
+                })
+                .collect(),
+        }
+    }
+
+    /// Records the overrides the graph was resolved with
+    pub fn with_overrides(mut self, overrides: &Overrides) -> Self {
+        self.overrides = overrides.to_map();
+        self
+    }
+
+    /// Rebuilds the locked graph without asking the registry
+    pub fn to_graph(&self) -> Result<Graph, Error> {
+        let mut graph = Graph::new();
+        for package in self.packages.values() {
+            graph.add_node(package.metadata());
+        }
+
+        let mut add_edges = |from: Option<&PackageId>, edges: &BTreeMap<String, LockedEdge>| {
+            for (name, edge) in edges {
+                let to = PackageId::new(edge.name.as_ref().unwrap_or(name), &edge.version);
+                if !graph.contains(&to) {
+                    return Err(Error::InvalidLockfile);
+                }
+                graph.add_edge(
+                    from,
+                    Edge {
+                        name: name.clone(),
+                        range: edge.range.clone(),
+                        kind: edge.kind,
+                        to,
+                        overridden: edge.overridden.clone(),
+                    },
+                );
+            }
+            Ok(())
+        };
+
+        add_edges(None, &self.dependencies)?;
+        for package in self.packages.values() {
+            let from = PackageId::new(&package.name, &package.version);
+            add_edges(Some(&from), &package.dependencies)?;
+        }
+
+        Ok(graph)
+    }
+
+    /// Whether the lockfile was resolved from exactly these dependencies
+    /// and overrides, i.e. package.json hasn't changed since it was written
+    pub fn matches(&self, dependencies: &[Dependency], overrides: &Overrides) -> bool {
+        let wanted: HashMap<&String, (&String, DependencyKind)> = dependencies
+            .iter()
+            .map(|dependency| (&dependency.name, (&dependency.range, dependency.kind)))
+            .collect();
+        let locked: HashMap<&String, (&String, DependencyKind)> = self
+            .dependencies
+            .iter()
+            .map(|(name, edge)| (name, (&edge.range, edge.kind)))
+            .collect();
+
+        wanted == locked && self.overrides == overrides.to_map()
+    }
+
+    /// The packages an install omitting `kinds` of dependencies leaves
+    /// out, going by their locked flags like npm, so pruning doesn't need
+    /// the registry
+    pub fn omitted(&self, kinds: &[DependencyKind]) -> HashSet<PackageId> {
+        let omits = |kind| kinds.contains(&kind);
+        self.packages
+            .values()
+            .filter(|package| {
+                (package.dev && omits(DependencyKind::Dev))
+                    || (package.optional && omits(DependencyKind::Optional))
+                    || (package.dev_optional
+                        && omits(DependencyKind::Dev)
+                        && omits(DependencyKind::Optional))
+                    || (package.peer && omits(DependencyKind::Peer))
+            })
+            .map(|package| PackageId::new(&package.name, &package.version))
+            .collect()
+    }
+
+    /// Every locked package, for seeding a [crate::Resolver]
+    pub fn locked_packages(&self) -> Vec<Metadata> {
+        self.packages
+            .values()
+            .map(LockedPackage::metadata)
+            .collect()
+    }
+
+    /// The git packages locked for each `(name, spec)` dependency of the
+    /// project and its packages, for [crate::Resolver::with_locked_git]
+    pub fn locked_git(&self) -> HashMap<(String, String), Metadata> {
+        let edges = self.dependencies.iter().chain(
+            self.packages
+                .values()
+                .flat_map(|package| &package.dependencies),
+        );
+        edges
+            .filter_map(|(name, edge)| {
+                let to = format!("{}@{}", edge.name.as_ref().unwrap_or(name), edge.version);
+                let package = self.packages.get(&to)?;
+                git::parse_resolved(&package.name, &package.resolved)?;
+                // Overridden edges were resolved with the override
+                let spec = edge.overridden.as_ref().unwrap_or(&edge.range);
+                Some(((name.clone(), spec.clone()), package.metadata()))
+            })
+            .collect()
+    }
+
+    /// Reads a lockfile, returning `None` if it doesn't exist
+    pub fn read(path: &Path) -> Result<Option<Self>, Error> {
+        let contents = match fs::read_to_string(path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(None),
+            Err(_) => return Err(Error::IoError),
+        };
+
+        match serde_json::from_str(&contents) {
+            Ok(lockfile) => Ok(Some(lockfile)),
+            Err(_) => Err(Error::InvalidLockfile),
+        }
+    }
+
+    pub fn to_json(&self) -> String {
+        let mut contents = serde_json::to_string_pretty(self).unwrap();
+        contents.push('\n');
+        contents
+    }
+
+    /// Writes the lockfile, leaving the file untouched if nothing changed
+    pub fn write(&self, path: &Path) -> Result<(), Error> {
+        let contents = self.to_json();
+        if fs::read_to_string(path).ok().as_ref() == Some(&contents) {
+            return Ok(());
+        }
+        fs::write(path, contents).map_err(|_| Error::IoError)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::metadata;
+
+    fn graph() -> Graph {
+        let mut graph = Graph::new();
+        let a = PackageId::new("a", "1.0.0");
+        let b = PackageId::new("b", "2.0.0");
+        graph.add_node(metadata("a", "1.0.0", &[("b", "^2.0.0")]));
+        graph.add_node(metadata("b", "2.0.0", &[("a", "1")]));
+        graph.add_edge(
+            None,
+            Edge {
+                name: String::from("a"),
+                range: String::from("^1.0.0"),
+                kind: DependencyKind::Dev,
+                to: a.clone(),
+                overridden: None,
+            },
+        );
+        graph.add_edge(
+            Some(&a),
+            Edge {
+                name: String::from("b"),
+                range: String::from("^2.0.0"),
+                kind: DependencyKind::Prod,
+                to: b.clone(),
+                overridden: None,
+            },
+        );
+        graph.add_edge(
+            Some(&b),
+            Edge {
+                name: String::from("a"),
+                range: String::from("1"),
+                kind: DependencyKind::Prod,
+                to: a,
+                overridden: None,
+            },
+        );
+        graph
+    }
+
+    #[test]
+    fn round_trips_graph() {
+        let lockfile = Lockfile::from_graph(&graph());
+        let parsed: Lockfile = serde_json::from_str(&lockfile.to_json()).unwrap();
+        assert_eq!(parsed, lockfile);
+
+        let rebuilt = parsed.to_graph().unwrap();
+        assert_eq!(rebuilt.roots(), graph().roots());
+        assert_eq!(Lockfile::from_graph(&rebuilt), lockfile);
+    }
+
+    #[test]
+    fn deterministic_output() {
+        assert_eq!(
+            Lockfile::from_graph(&graph()).to_json(),
+            r#"{
+  "lockfileVersion": 1,
+  "dependencies": {
+    "a": {
+      "range": "^1.0.0",
+      "version": "1.0.0",
+      "kind": "dev"
+    }
+  },
+  "packages": {
+    "a@1.0.0": {
+      "name": "a",
+      "version": "1.0.0",
+      "resolved": "https://registry.npmjs.org/a/-/a-1.0.0.tgz",
+      "dev": true,
+      "dependencies": {
+        "b": {
+          "range": "^2.0.0",
+          "version": "2.0.0"
+        }
+      }
+    },
+    "b@2.0.0": {
+      "name": "b",
+      "version": "2.0.0",
+      "resolved": "https://registry.npmjs.org/b/-/b-2.0.0.tgz",
+      "dev": true,
+      "dependencies": {
+        "a": {
+          "range": "1",
+          "version": "1.0.0"
+        }
+      }
+    }
+  }
+}
+"#
+        );
+    }
+
+    #[test]
+    fn detects_changed_manifest() {
+        let lockfile = Lockfile::from_graph(&graph());
+        let none = Overrides::default();
+        assert!(lockfile.matches(
+            &[Dependency::new("a", "^1.0.0", DependencyKind::Dev)],
+            &none
+        ));
+        assert!(!lockfile.matches(
+            &[Dependency::new("a", "^1.0.0", DependencyKind::Prod)],
+            &none
+        ));
+        assert!(!lockfile.matches(
+            &[Dependency::new("a", "^1.1.0", DependencyKind::Dev)],
+            &none
+        ));
+        assert!(!lockfile.matches(&[], &none));
+    }
+
+    #[test]
+    fn detects_changed_overrides() {
+        let dependencies = [Dependency::new("a", "^1.0.0", DependencyKind::Dev)];
+        let manifest = serde_json::from_str(r#"{ "overrides": { "b": "2.0.0" } }"#).unwrap();
+        let overrides = Overrides::from_manifest(&manifest).unwrap();
+
+        let lockfile = Lockfile::from_graph(&graph());
+        assert!(!lockfile.matches(&dependencies, &overrides));
+
+        let lockfile = lockfile.with_overrides(&overrides);
+        assert!(lockfile.matches(&dependencies, &overrides));
+        assert!(!lockfile.matches(&dependencies, &Overrides::default()));
+        let parsed: Lockfile = serde_json::from_str(&lockfile.to_json()).unwrap();
+        assert_eq!(parsed.overrides["b"], "2.0.0");
+    }
+
+    #[test]
+    fn round_trips_optional_peers() {
+        let mut graph = Graph::new();
+        let plugin = PackageId::new("plugin", "1.0.0");
+        let mut metadata = metadata("plugin", "1.0.0", &[]);
+        metadata.peer_dependencies = Some(HashMap::from([(
+            String::from("react"),
+            String::from(">=17"),
+        )]));
+        metadata.peer_dependencies_meta = Some(HashMap::from([(
+            String::from("react"),
+            PeerDependencyMeta { optional: true },
+        )]));
+        graph.add_node(metadata);
+        graph.add_edge(
+            None,
+            Edge {
+                name: String::from("plugin"),
+                range: String::from("^1.0.0"),
+                kind: DependencyKind::Prod,
+                to: plugin.clone(),
+                overridden: None,
+            },
+        );
+
+        let lockfile = Lockfile::from_graph(&graph);
+        let parsed: Lockfile = serde_json::from_str(&lockfile.to_json()).unwrap();
+
+        assert_eq!(parsed, lockfile);
+        let rebuilt = parsed.to_graph().unwrap();
+        assert!(rebuilt
+            .node(&plugin)
+            .unwrap()
+            .metadata
+            .is_optional_peer("react"));
+    }
+
+    #[test]
+    fn rejects_dangling_edges() {
+        let mut lockfile = Lockfile::from_graph(&graph());
+        lockfile.packages.remove("b@2.0.0");
+        assert_eq!(lockfile.to_graph(), Err(Error::InvalidLockfile));
+    }
+
+    #[test]
+    fn locks_aliases_to_the_real_package() {
+        let mut graph = Graph::new();
+        let lodash = PackageId::new("lodash", "4.17.21");
+        graph.add_node(metadata("lodash", "4.17.21", &[]));
+        graph.add_edge(
+            None,
+            Edge {
+                name: String::from("lodash4"),
+                range: String::from("npm:lodash@^4"),
+                kind: DependencyKind::Prod,
+                to: lodash.clone(),
+                overridden: None,
+            },
+        );
+
+        let lockfile = Lockfile::from_graph(&graph);
+
+        assert_eq!(
+            lockfile.dependencies["lodash4"].name.as_deref(),
+            Some("lodash")
+        );
+        let rebuilt = lockfile.to_graph().unwrap();
+        assert_eq!(rebuilt.roots()[0].name, "lodash4");
+        assert_eq!(rebuilt.roots()[0].to, lodash);
+    }
+
+    #[test]
+    fn flags_packages_only_some_installs_need() {
+        let mut graph = Graph::new();
+        let edge = |name: &str, kind| Edge {
+            name: name.to_string(),
+            range: String::from("^1.0.0"),
+            kind,
+            to: PackageId::new(name, "1.0.0"),
+            overridden: None,
+        };
+        for name in ["prod", "dev", "optional", "shared", "peer"] {
+            graph.add_node(metadata(name, "1.0.0", &[]));
+        }
+        graph.add_edge(None, edge("prod", DependencyKind::Prod));
+        graph.add_edge(None, edge("dev", DependencyKind::Dev));
+        graph.add_edge(None, edge("optional", DependencyKind::Optional));
+        for from in ["dev", "optional"] {
+            let from = PackageId::new(from, "1.0.0");
+            graph.add_edge(Some(&from), edge("shared", DependencyKind::Prod));
+        }
+        let prod = PackageId::new("prod", "1.0.0");
+        graph.add_edge(Some(&prod), edge("peer", DependencyKind::Peer));
+
+        let lockfile = Lockfile::from_graph(&graph);
+
+        let flags = |key: &str| {
+            let package = &lockfile.packages[key];
+            (
+                package.dev,
+                package.optional,
+                package.dev_optional,
+                package.peer,
+            )
+        };
+        assert_eq!(flags("prod@1.0.0"), (false, false, false, false));
+        assert_eq!(flags("dev@1.0.0"), (true, false, false, false));
+        assert_eq!(flags("optional@1.0.0"), (false, true, false, false));
+        assert_eq!(flags("shared@1.0.0"), (false, false, true, false));
+        assert_eq!(flags("peer@1.0.0"), (false, false, false, true));
+        let mut parsed: Lockfile = serde_json::from_str(&lockfile.to_json()).unwrap();
+        assert_eq!(parsed, lockfile);
+
+        // Pruning only looks at the flags, not at the graph
+        let omitted = |lockfile: &Lockfile, kinds: &[DependencyKind]| {
+            let mut omitted: Vec<String> = lockfile
+                .omitted(kinds)
+                .iter()
+                .map(|id| id.name.clone())
+                .collect();
+            omitted.sort();
+            omitted
+        };
+        assert_eq!(omitted(&parsed, &[DependencyKind::Dev]), vec!["dev"]);
+        assert_eq!(
+            omitted(&parsed, &[DependencyKind::Dev, DependencyKind::Optional]),
+            vec!["dev", "optional", "shared"]
+        );
+        assert_eq!(omitted(&parsed, &[DependencyKind::Peer]), vec!["peer"]);
+        parsed.packages.get_mut("prod@1.0.0").unwrap().dev = true;
+        assert_eq!(
+            omitted(&parsed, &[DependencyKind::Dev]),
+            vec!["dev", "prod"]
+        );
+    }
+}
@@ -0,0 +1,95 @@
+use std::collections::HashMap;
+
+use serde::Deserialize;
+use serde_json::Value;
+
+use crate::{Dependency, DependencyKind, Dist, Metadata, PeerDependencyMeta, Platform};
+
+/// The parts of a package.json that matter when installing
+#[derive(Deserialize, PartialEq, Debug, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct Manifest {
+    pub name: Option<String>,
+    pub version: Option<String>,
+    pub dependencies: Option<HashMap<String, String>>,
+    pub dev_dependencies: Option<HashMap<String, String>>,
+    pub optional_dependencies: Option<HashMap<String, String>>,
+    pub peer_dependencies: Option<HashMap<String, String>>,
+    pub peer_dependencies_meta: Option<HashMap<String, PeerDependencyMeta>>,
+    pub scripts: Option<HashMap<String, String>>,
+    /// npm's overrides, which nest like the dependency tree
+    pub overrides: Option<Value>,
+    /// Yarn's resolutions, keyed by paths like `a/**/b`
+    pub resolutions: Option<HashMap<String, String>>,
+    #[serde(flatten)]
+    pub platform: Platform,
+}
+
+impl Manifest {
+    /// Every dependency of the manifest, sorted by name. When a package is
+    /// listed in several maps, later maps take precedence (so
+    /// `optionalDependencies` wins over `dependencies`, like it does in npm)
+    pub fn all_dependencies(&self) -> Vec<Dependency> {
+        let mut merged: HashMap<&String, Dependency> = HashMap::new();
+        for (deps, kind) in [
+            (&self.peer_dependencies, DependencyKind::Peer),
+            (&self.dev_dependencies, DependencyKind::Dev),
+            (&self.dependencies, DependencyKind::Prod),
+            (&self.optional_dependencies, DependencyKind::Optional),
+        ] {
+            for (name, range) in deps.iter().flatten() {
+                merged.insert(name, Dependency::new(name, range, kind));
+            }
+        }
+
+        let mut result: Vec<Dependency> = merged.into_values().collect();
+        result.sort_by(|a, b| a.name.cmp(&b.name));
+        result
+    }
+
+    /// The metadata a registry would publish for this package, for packages
+    /// installed from somewhere else. `name` is used when the manifest
+    /// doesn't have one
+    pub(crate) fn into_metadata(self, name: &str, dist: Dist) -> Metadata {
+        let mut dependencies = self.dependencies.unwrap_or_default();
+        dependencies.extend(self.optional_dependencies.clone().unwrap_or_default());
+
+        Metadata {
+            name: self.name.unwrap_or_else(|| name.to_string()),
+            version: self.version.unwrap_or_else(|| String::from("0.0.0")),
+            dependencies: Some(dependencies),
+            peer_dependencies: self.peer_dependencies,
+            peer_dependencies_meta: self.peer_dependencies_meta,
+            optional_dependencies: self.optional_dependencies,
+            platform: self.platform,
+            dist,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn merges_dependency_maps() {
+        let manifest: Manifest = serde_json::from_str(
+            r#"{
+                "name": "app",
+                "dependencies": { "express": "^4.18.1", "debug": "2.6.9" },
+                "devDependencies": { "typescript": "^4.7.0" },
+                "optionalDependencies": { "debug": "^2.0.0" }
+            }"#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            manifest.all_dependencies(),
+            vec![
+                Dependency::new("debug", "^2.0.0", DependencyKind::Optional),
+                Dependency::new("express", "^4.18.1", DependencyKind::Prod),
+                Dependency::new("typescript", "^4.7.0", DependencyKind::Dev),
+            ]
+        );
+    }
+}
@@ -0,0 +1,408 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+
+use crate::Error;
+
+/// The name of npm's config files
+pub const NPMRC_NAME: &str = ".npmrc";
+
+/// The public npm registry, used unless a config file says otherwise
+pub const DEFAULT_REGISTRY: &str = "https://registry.npmjs.org";
+
+/// Credentials for one registry, from `//host/path/:_authToken` and friends
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+struct Credentials {
+    token: Option<String>,
+    auth: Option<String>,
+    always_auth: bool,
+}
+
+impl Credentials {
+    /// The `Authorization` header these credentials are sent as
+    fn header(&self) -> Option<String> {
+        match (&self.token, &self.auth) {
+            (Some(token), _) => Some(format!("Bearer {token}")),
+            (None, Some(auth)) => Some(format!("Basic {auth}")),
+            (None, None) => None,
+        }
+    }
+}
+
+/// Registry settings from npm's config files: which registry each package
+/// is fetched from, and the credentials sent along.
+///
+/// Files are layered like npm does, so the project's `.npmrc` wins over
+/// the user's, which wins over the global one
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Npmrc {
+    registry: String,
+    /// Registries of `@scope/*` packages, keyed by `@scope`
+    scopes: HashMap<String, String>,
+    /// Keyed by "nerf dart", the registry URL without its scheme
+    /// (`//host/path/`)
+    credentials: HashMap<String, Credentials>,
+    /// Unscoped `_authToken`/`_auth`, which belong to the default registry
+    default_credentials: Credentials,
+    always_auth: bool,
+}
+
+impl Default for Npmrc {
+    fn default() -> Self {
+        Self::new(DEFAULT_REGISTRY)
+    }
+}
+
+/// Replaces `${NAME}` with the environment variable `NAME`. Like npm,
+/// unset variables are left alone and `\${NAME}` escapes the substitution
+fn substitute(value: &str, env: &impl Fn(&str) -> Option<String>) -> String {
+    let mut substituted = String::new();
+    let mut rest = value;
+    while let Some(start) = rest.find("${") {
+        let end = match rest[start..].find('}') {
+            Some(end) => start + end,
+            None => break,
+        };
+        let escapes = rest[..start].len() - rest[..start].trim_end_matches('\\').len();
+        substituted.push_str(&rest[..start - escapes]);
+        substituted.push_str(&"\\".repeat(escapes / 2));
+
+        let variable = &rest[start..=end];
+        match env(&variable[2..variable.len() - 1]) {
+            Some(value) if escapes.is_multiple_of(2) => substituted.push_str(&value),
+            _ => substituted.push_str(variable),
+        }
+        rest = &rest[end + 1..];
+    }
+    substituted.push_str(rest);
+    substituted
+}
+
+/// `//host/path/` out of `https://host/path/file`, which is how npm keys
+/// credentials so they're never sent to another host
+fn nerf_dart(url: &str) -> Option<String> {
+    let url = url.split(['?', '#']).next()?;
+    let (_, rest) = url.split_once("//")?;
+    let path = match rest.rfind('/') {
+        Some(index) => &rest[..=index],
+        None => rest,
+    };
+    let path = if path.contains('/') {
+        path.to_string()
+    } else {
+        format!("{path}/")
+    };
+    Some(format!("//{path}"))
+}
+
+/// `url` with a trailing slash, as npm treats registry URLs as directories
+fn directory(url: &str) -> String {
+    format!("{}/", url.trim_end_matches('/'))
+}
+
+/// `//host/a/b/`, `//host/a/` and then `//host/`
+fn ancestors(nerf_dart: &str) -> impl Iterator<Item = &str> {
+    let host_end = nerf_dart[2..]
+        .find('/')
+        .map_or(nerf_dart.len(), |index| index + 3);
+    std::iter::successors(Some(nerf_dart), move |dart| {
+        if dart.len() <= host_end {
+            return None;
+        }
+        let parent = dart.trim_end_matches('/');
+        parent.rfind('/').map(|index| &parent[..=index])
+    })
+}
+
+fn is_true(value: &str) -> bool {
+    value == "true"
+}
+
+impl Npmrc {
+    /// Settings that fetch everything from `registry`, without credentials
+    pub fn new(registry: &str) -> Self {
+        Self {
+            registry: registry.trim_end_matches('/').to_string(),
+            scopes: HashMap::new(),
+            credentials: HashMap::new(),
+            default_credentials: Credentials::default(),
+            always_auth: false,
+        }
+    }
+
+    /// Reads the global, user and project config files, in that order.
+    ///
+    /// The global file is `NPM_CONFIG_GLOBALCONFIG` or
+    /// `$NPM_CONFIG_PREFIX/etc/npmrc`, and the user's `NPM_CONFIG_USERCONFIG`
+    /// or `~/.npmrc`. Missing files are skipped
+    pub fn load(project: &Path, home: Option<&Path>) -> Result<Self, Error> {
+        let var = |name: &str| std::env::var(name).ok();
+        let global = var("NPM_CONFIG_GLOBALCONFIG")
+            .map(PathBuf::from)
+            .unwrap_or_else(|| {
+                Path::new(&var("NPM_CONFIG_PREFIX").unwrap_or_else(|| String::from("/usr/local")))
+                    .join("etc")
+                    .join("npmrc")
+            });
+        let user = var("NPM_CONFIG_USERCONFIG")
+            .map(PathBuf::from)
+            .or_else(|| home.map(|home| home.join(NPMRC_NAME)));
+
+        let mut paths = vec![global];
+        paths.extend(user);
+        paths.push(project.join(NPMRC_NAME));
+        Self::read(&paths, var)
+    }
+
+    fn read(paths: &[PathBuf], env: impl Fn(&str) -> Option<String>) -> Result<Self, Error> {
+        let mut npmrc = Self::default();
+        for path in paths {
+            match fs::read_to_string(path) {
+                Ok(contents) => npmrc.parse(&contents, &env),
+                Err(e) if e.kind() == ErrorKind::NotFound => {}
+                Err(e) => {
+                    return Err(Error::Config {
+                        path: path.display().to_string(),
+                        cause: e.to_string(),
+                    })
+                }
+            }
+        }
+        Ok(npmrc)
+    }
+
+    /// Applies the settings of an ini file over the current ones. Settings
+    /// that have nothing to do with registries are ignored
+    fn parse(&mut self, contents: &str, env: &impl Fn(&str) -> Option<String>) {
+        for line in contents.lines() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with([';', '#']) {
+                continue;
+            }
+            let (key, value) = match line.split_once('=') {
+                Some((key, value)) => (key.trim(), value.trim()),
+                None => continue,
+            };
+            let unquoted = value
+                .strip_prefix('"')
+                .and_then(|value| value.strip_suffix('"'))
+                .or_else(|| {
+                    value
+                        .strip_prefix('\'')
+                        .and_then(|value| value.strip_suffix('\''))
+                })
+                .unwrap_or(value);
+            let key = substitute(key, env);
+            let value = substitute(unquoted, env);
+
+            if let Some((dart, setting)) =
+                key.strip_prefix("//").and_then(|key| key.rsplit_once(':'))
+            {
+                let credentials = self
+                    .credentials
+                    .entry(directory(&format!("//{dart}")))
+                    .or_default();
+                match setting {
+                    "_authToken" => credentials.token = Some(value),
+                    "_auth" => credentials.auth = Some(value),
+                    "always-auth" => credentials.always_auth = is_true(&value),
+                    _ => {}
+                }
+                continue;
+            }
+
+            match key.as_str() {
+                "registry" => self.registry = value.trim_end_matches('/').to_string(),
+                "_authToken" => self.default_credentials.token = Some(value),
+                "_auth" => self.default_credentials.auth = Some(value),
+                "always-auth" => self.always_auth = is_true(&value),
+                _ => {
+                    if let Some(scope) = key
+                        .strip_suffix(":registry")
+                        .filter(|scope| scope.starts_with('@'))
+                    {
+                        self.scopes
+                            .insert(scope.to_string(), value.trim_end_matches('/').to_string());
+                    }
+                }
+            }
+        }
+    }
+
+    /// Settings read from `contents`, without environment variables
+    #[cfg(test)]
+    pub(crate) fn parsed(contents: &str) -> Self {
+        let mut npmrc = Self::default();
+        npmrc.parse(contents, &|_| None);
+        npmrc
+    }
+
+    /// The default registry, without a trailing slash
+    pub fn registry(&self) -> &str {
+        &self.registry
+    }
+
+    /// The registry `name` is fetched from, which depends on its scope
+    pub fn registry_for(&self, name: &str) -> &str {
+        name.split_once('/')
+            .filter(|(scope, _)| scope.starts_with('@'))
+            .and_then(|(scope, _)| self.scopes.get(scope))
+            .unwrap_or(&self.registry)
+    }
+
+    /// The credentials of the longest registry path `nerf_dart` is under
+    fn credentials(&self, nerf_dart: &str) -> Option<&Credentials> {
+        let default = nerf_dart_of_registry(&self.registry);
+        ancestors(nerf_dart).find_map(|dart| {
+            self.credentials
+                .get(dart)
+                .filter(|credentials| credentials.header().is_some())
+                .or_else(|| {
+                    Some(&self.default_credentials)
+                        .filter(|credentials| credentials.header().is_some())
+                        .filter(|_| default.as_deref() == Some(dart))
+                })
+        })
+    }
+
+    /// The `Authorization` header to send with a request for `url`, made on
+    /// behalf of the package `name`.
+    ///
+    /// Credentials are only sent to the registry they're configured for,
+    /// unless `always-auth` is set: then the credentials of the registry of
+    /// `name` also go to tarballs it hosts elsewhere
+    pub fn authorization(&self, name: &str, url: &str) -> Option<String> {
+        if let Some(credentials) = nerf_dart(url).and_then(|dart| self.credentials(&dart)) {
+            return credentials.header();
+        }
+
+        let credentials = self.credentials(&nerf_dart_of_registry(self.registry_for(name))?)?;
+        if self.always_auth || credentials.always_auth {
+            credentials.header()
+        } else {
+            None
+        }
+    }
+}
+
+fn nerf_dart_of_registry(registry: &str) -> Option<String> {
+    nerf_dart(&directory(registry))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn env(name: &str) -> Option<String> {
+        match name {
+            "TOKEN" => Some(String::from("secret")),
+            _ => None,
+        }
+    }
+
+    fn npmrc(contents: &str) -> Npmrc {
+        let mut npmrc = Npmrc::default();
+        npmrc.parse(contents, &env);
+        npmrc
+    }
+
+    #[test]
+    fn substitutes_environment_variables() {
+        assert_eq!(substitute("${TOKEN}", &env), "secret");
+        assert_eq!(substitute("a-${TOKEN}-b", &env), "a-secret-b");
+        assert_eq!(substitute("${MISSING}", &env), "${MISSING}");
+        assert_eq!(substitute("\\${TOKEN}", &env), "${TOKEN}");
+        assert_eq!(substitute("\\\\${TOKEN}", &env), "\\secret");
+    }
+
+    #[test]
+    fn routes_scopes_to_their_registry() {
+        let npmrc = npmrc(
+            r#"
+; a comment
+registry = https://registry.example.com/
+@ourcompany:registry="https://npm.ourcompany.com/api/npm/"
+"#,
+        );
+
+        assert_eq!(npmrc.registry_for("a"), "https://registry.example.com");
+        assert_eq!(
+            npmrc.registry_for("@ourcompany/a"),
+            "https://npm.ourcompany.com/api/npm"
+        );
+        assert_eq!(
+            npmrc.registry_for("@other/a"),
+            "https://registry.example.com"
+        );
+    }
+
+    #[test]
+    fn sends_credentials_to_their_registry_only() {
+        let npmrc = npmrc(
+            r#"
+@ourcompany:registry=https://npm.ourcompany.com/api/npm/
+//npm.ourcompany.com/api/npm/:_authToken=${TOKEN}
+//registry.npmjs.org/:_auth=dXNlcjpwYXNz
+"#,
+        );
+
+        assert_eq!(
+            npmrc.authorization(
+                "@ourcompany/a",
+                "https://npm.ourcompany.com/api/npm/@ourcompany%2fa"
+            ),
+            Some(String::from("Bearer secret"))
+        );
+        assert_eq!(
+            npmrc.authorization("a", "https://registry.npmjs.org/a/-/a-1.0.0.tgz"),
+            Some(String::from("Basic dXNlcjpwYXNz"))
+        );
+        assert_eq!(
+            npmrc.authorization("@ourcompany/a", "https://cdn.example.com/a.tgz"),
+            None
+        );
+        assert_eq!(
+            npmrc.authorization("a", "https://npm.ourcompany.com/other/a"),
+            None
+        );
+    }
+
+    #[test]
+    fn always_auth_follows_tarballs() {
+        let npmrc = npmrc(
+            r#"
+registry=https://npm.ourcompany.com/
+_authToken=${TOKEN}
+always-auth=true
+"#,
+        );
+
+        assert_eq!(
+            npmrc.authorization("a", "https://npm.ourcompany.com/a"),
+            Some(String::from("Bearer secret"))
+        );
+        assert_eq!(
+            npmrc.authorization("a", "https://cdn.example.com/a.tgz"),
+            Some(String::from("Bearer secret"))
+        );
+    }
+
+    #[test]
+    fn layers_files() {
+        let dir = tempdir::TempDir::new("vortex-npmrc").unwrap();
+        let global = dir.path().join("npmrc");
+        let project = dir.path().join(NPMRC_NAME);
+        fs::write(
+            &global,
+            "registry=https://global.example.com\n@a:registry=https://a.example.com",
+        )
+        .unwrap();
+        fs::write(&project, "registry=https://project.example.com").unwrap();
+
+        let npmrc = Npmrc::read(&[global, dir.path().join("missing"), project], env).unwrap();
+
+        assert_eq!(npmrc.registry(), "https://project.example.com");
+        assert_eq!(npmrc.registry_for("@a/b"), "https://a.example.com");
+    }
+}
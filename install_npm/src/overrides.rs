@@ -0,0 +1,435 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fmt::{self, Display};
+
+use node_semver::{Range, Version};
+use serde_json::Value;
+
+use crate::{Dependency, Error, Manifest, PackageId};
+
+/// One step of the path to an overridden package
+#[derive(Clone, PartialEq, Eq, Debug)]
+enum Selector {
+    /// Any number of packages, like `**` in a Yarn resolution
+    Any,
+    /// A package called `name`, optionally only at versions in `range`
+    Package { name: String, range: Option<String> },
+}
+
+impl Selector {
+    /// Parses `name` or `name@range`, keeping the leading `@` of scoped
+    /// packages
+    fn parse(key: &str) -> Self {
+        if key == "**" {
+            return Selector::Any;
+        }
+        match key.get(1..).and_then(|rest| rest.find('@')) {
+            Some(index) => Selector::Package {
+                name: key[..index + 1].to_string(),
+                range: Some(key[index + 2..].to_string()),
+            },
+            None => Selector::Package {
+                name: key.to_string(),
+                range: None,
+            },
+        }
+    }
+
+    /// Whether the resolved package `id` is matched, for packages above the
+    /// overridden one
+    fn matches_package(&self, id: &PackageId) -> bool {
+        match self {
+            Selector::Any => true,
+            Selector::Package { name, range } => {
+                name == &id.name
+                    && range.as_ref().is_none_or(|range| {
+                        match (Range::parse(range), Version::parse(&id.version)) {
+                            (Ok(range), Ok(version)) => range.satisfies(&version),
+                            _ => false,
+                        }
+                    })
+            }
+        }
+    }
+
+    /// Whether `dependency` is matched before it is resolved, for the
+    /// overridden package itself
+    fn matches_dependency(&self, dependency: &Dependency) -> bool {
+        match self {
+            Selector::Any => false,
+            Selector::Package { name, range } => {
+                name == &dependency.name
+                    && range.as_ref().is_none_or(|range| {
+                        match (Range::parse(range), Range::parse(&dependency.range)) {
+                            (Ok(range), Ok(wanted)) => range.allows_any(&wanted),
+                            _ => range == &dependency.range,
+                        }
+                    })
+            }
+        }
+    }
+}
+
+impl Display for Selector {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Selector::Any => write!(f, "**"),
+            Selector::Package { name, range: None } => write!(f, "{name}"),
+            Selector::Package {
+                name,
+                range: Some(range),
+            } => write!(f, "{name}@{range}"),
+        }
+    }
+}
+
+/// Forces every dependency on `target` below `ancestors` to resolve to
+/// `spec` instead of the range it asks for
+#[derive(Clone, PartialEq, Eq, Debug)]
+struct Rule {
+    /// The packages the overridden one must be below, ending right above
+    /// it. Any packages may come before the first one
+    ancestors: Vec<Selector>,
+    target: Selector,
+    spec: String,
+}
+
+impl Rule {
+    /// Rules with more packages in their path are more specific, and win
+    fn specificity(&self) -> usize {
+        self.ancestors
+            .iter()
+            .filter(|selector| **selector != Selector::Any)
+            .count()
+    }
+}
+
+impl Display for Rule {
+    /// Written like a Yarn resolution, e.g. `a/**/b`
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for selector in &self.ancestors {
+            write!(f, "{selector}/")?;
+        }
+        write!(f, "{}", self.target)
+    }
+}
+
+/// `positions` along with every position reached from them by skipping a
+/// `**`, which may match no packages at all
+fn skip_any(selectors: &[Selector], mut positions: BTreeSet<usize>) -> BTreeSet<usize> {
+    let mut position = 0;
+    while position < selectors.len() {
+        if positions.contains(&position) && selectors[position] == Selector::Any {
+            positions.insert(position + 1);
+        }
+        position += 1;
+    }
+    positions
+}
+
+/// How far along the path of each rule a walk from the project down to a
+/// package has got. Any packages may come before the start of a path, so
+/// every rule can also start over at each package. A package's overrides
+/// only depend on this, not on the rest of its path
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
+pub(crate) struct Walk(Vec<BTreeSet<usize>>);
+
+fn invalid(key: &str, cause: impl Into<String>) -> Error {
+    Error::InvalidOverride {
+        key: key.to_string(),
+        cause: cause.into(),
+    }
+}
+
+/// npm's `overrides` and Yarn's `resolutions` from the project's
+/// package.json, which replace the ranges transitive dependencies ask for
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+pub struct Overrides {
+    rules: Vec<Rule>,
+}
+
+impl Overrides {
+    /// Reads the overrides of the project's package.json. `$name` specs
+    /// refer to the project's own dependency on `name`, like in npm
+    pub fn from_manifest(manifest: &Manifest) -> Result<Self, Error> {
+        let roots: HashMap<String, String> = manifest
+            .all_dependencies()
+            .into_iter()
+            .map(|dependency| (dependency.name, dependency.range))
+            .collect();
+
+        let mut overrides = Overrides::default();
+        if let Some(value) = &manifest.overrides {
+            overrides.add_npm(value, &[], &roots)?;
+        }
+        // Sorted, as package.json is read into a map without an order
+        let resolutions: BTreeMap<&String, &String> =
+            manifest.resolutions.iter().flatten().collect();
+        for (pattern, spec) in resolutions {
+            overrides.add_yarn(pattern, spec)?;
+        }
+        Ok(overrides)
+    }
+
+    /// Adds an object of npm's `overrides`, nested below `ancestors`
+    fn add_npm(
+        &mut self,
+        value: &Value,
+        ancestors: &[Selector],
+        roots: &HashMap<String, String>,
+    ) -> Result<(), Error> {
+        let object = match value {
+            Value::Object(object) => object,
+            _ => return Err(invalid("overrides", "overrides must be an object")),
+        };
+
+        for (key, value) in object {
+            if key == "." {
+                continue;
+            }
+            let target = Selector::parse(key);
+            let spec = match value {
+                Value::String(spec) => Some(spec),
+                // `"a": { ".": "1.0.0", "b": "2.0.0" }` overrides a, and b
+                // anywhere below it
+                Value::Object(nested) => {
+                    let mut below = ancestors.to_vec();
+                    below.extend([target.clone(), Selector::Any]);
+                    self.add_npm(value, &below, roots)?;
+                    match nested.get(".") {
+                        Some(Value::String(spec)) => Some(spec),
+                        Some(_) => return Err(invalid(key, "\".\" must be a string")),
+                        None => None,
+                    }
+                }
+                _ => return Err(invalid(key, "overrides must be strings or objects")),
+            };
+
+            if let Some(spec) = spec {
+                let spec = match spec.strip_prefix('$') {
+                    Some(name) => match roots.get(name) {
+                        Some(range) => range.clone(),
+                        None => {
+                            return Err(invalid(
+                                key,
+                                format!("the project doesn't depend on {name}"),
+                            ))
+                        }
+                    },
+                    None => spec.clone(),
+                };
+                self.rules.push(Rule {
+                    ancestors: ancestors.to_vec(),
+                    target,
+                    spec,
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// Adds one of Yarn's `resolutions`, like `b`, `a/b` or `a/**/b`
+    fn add_yarn(&mut self, pattern: &str, spec: &str) -> Result<(), Error> {
+        let mut selectors = Vec::new();
+        let mut parts = pattern.split('/');
+        while let Some(part) = parts.next() {
+            let key = match part.starts_with('@') {
+                true => match parts.next() {
+                    Some(name) => format!("{part}/{name}"),
+                    None => return Err(invalid(pattern, "scoped names need a package name")),
+                },
+                false => part.to_string(),
+            };
+            if key.is_empty() {
+                return Err(invalid(pattern, "empty package name"));
+            }
+            selectors.push(Selector::parse(&key));
+        }
+
+        let target = match selectors.pop() {
+            Some(Selector::Any) | None => {
+                return Err(invalid(pattern, "resolutions must end with a package name"))
+            }
+            Some(target) => target,
+        };
+        self.rules.push(Rule {
+            ancestors: selectors,
+            target,
+            spec: spec.to_string(),
+        });
+        Ok(())
+    }
+
+    /// The spec `dependency` should be resolved with instead of its range,
+    /// when it is below `ancestors` (from a dependency of the project down
+    /// to the package depending on it). The most specific rule wins
+    pub fn find(&self, ancestors: &[PackageId], dependency: &Dependency) -> Option<&str> {
+        let walk = ancestors
+            .iter()
+            .fold(self.start(), |walk, id| self.step(&walk, id));
+        self.find_at(&walk, dependency)
+    }
+
+    /// Where every walk starts, at the project itself
+    pub(crate) fn start(&self) -> Walk {
+        Walk(
+            self.rules
+                .iter()
+                .map(|rule| skip_any(&rule.ancestors, BTreeSet::from([0])))
+                .collect(),
+        )
+    }
+
+    /// `walk` after going down to the package `id`
+    pub(crate) fn step(&self, walk: &Walk, id: &PackageId) -> Walk {
+        let positions = self.rules.iter().zip(&walk.0).map(|(rule, positions)| {
+            let mut next = BTreeSet::from([0]);
+            for &position in positions {
+                match rule.ancestors.get(position) {
+                    Some(Selector::Any) => {
+                        next.insert(position);
+                    }
+                    Some(selector) if selector.matches_package(id) => {
+                        next.insert(position + 1);
+                    }
+                    _ => {}
+                }
+            }
+            skip_any(&rule.ancestors, next)
+        });
+        Walk(positions.collect())
+    }
+
+    /// Like [Overrides::find], for a package `walk` has got to
+    pub(crate) fn find_at(&self, walk: &Walk, dependency: &Dependency) -> Option<&str> {
+        self.rules
+            .iter()
+            .zip(&walk.0)
+            .filter(|(rule, _)| rule.target.matches_dependency(dependency))
+            .filter(|(rule, positions)| positions.contains(&rule.ancestors.len()))
+            // The first of equally specific rules wins
+            .rev()
+            .max_by_key(|(rule, _)| rule.specificity())
+            .map(|(rule, _)| rule.spec.as_str())
+    }
+
+    /// Every rule, written like Yarn resolutions, along with its spec. Kept
+    /// in the lockfile, so changing an override resolves again
+    pub fn to_map(&self) -> BTreeMap<String, String> {
+        self.rules
+            .iter()
+            .map(|rule| (rule.to_string(), rule.spec.clone()))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::DependencyKind;
+
+    fn manifest(json: &str) -> Manifest {
+        serde_json::from_str(json).unwrap()
+    }
+
+    fn dependency(name: &str, range: &str) -> Dependency {
+        Dependency::new(name, range, DependencyKind::Prod)
+    }
+
+    fn path(ids: &[(&str, &str)]) -> Vec<PackageId> {
+        ids.iter()
+            .map(|(name, version)| PackageId::new(name, version))
+            .collect()
+    }
+
+    #[test]
+    fn nests_npm_overrides() {
+        let overrides = Overrides::from_manifest(&manifest(
+            r#"{
+                "dependencies": { "react": "^18.2.0" },
+                "overrides": {
+                    "minimist": "1.2.8",
+                    "a@^1": { ".": "1.0.1", "minimist": "1.2.6" },
+                    "react": "$react"
+                }
+            }"#,
+        ))
+        .unwrap();
+        let a = path(&[("app", "1.0.0"), ("a", "1.0.1")]);
+
+        assert_eq!(
+            overrides.find(&[], &dependency("minimist", "^1.0.0")),
+            Some("1.2.8")
+        );
+        assert_eq!(
+            overrides.find(&a, &dependency("minimist", "^1.0.0")),
+            Some("1.2.6")
+        );
+        assert_eq!(
+            overrides.find(&path(&[("a", "2.0.0")]), &dependency("minimist", "^1.0.0")),
+            Some("1.2.8")
+        );
+        assert_eq!(
+            overrides.find(&[], &dependency("a", "^1.0.0")),
+            Some("1.0.1")
+        );
+        assert_eq!(overrides.find(&[], &dependency("a", "^2.0.0")), None);
+        assert_eq!(
+            overrides.find(&a, &dependency("react", "^17.0.0")),
+            Some("^18.2.0")
+        );
+        assert_eq!(overrides.find(&a, &dependency("lodash", "^4.0.0")), None);
+        assert_eq!(
+            overrides.to_map().keys().collect::<Vec<_>>(),
+            vec!["a@^1", "a@^1/**/minimist", "minimist", "react"]
+        );
+    }
+
+    #[test]
+    fn matches_yarn_resolutions() {
+        let overrides = Overrides::from_manifest(&manifest(
+            r#"{
+                "resolutions": {
+                    "**/lodash": "4.17.21",
+                    "a/@scope/b": "2.0.0",
+                    "a/**/c": "3.0.0"
+                }
+            }"#,
+        ))
+        .unwrap();
+        let a = path(&[("a", "1.0.0")]);
+        let deep = path(&[("a", "1.0.0"), ("x", "1.0.0")]);
+
+        assert_eq!(
+            overrides.find(&deep, &dependency("lodash", "^4.0.0")),
+            Some("4.17.21")
+        );
+        assert_eq!(
+            overrides.find(&a, &dependency("@scope/b", "^1.0.0")),
+            Some("2.0.0")
+        );
+        assert_eq!(
+            overrides.find(&deep, &dependency("@scope/b", "^1.0.0")),
+            None
+        );
+        assert_eq!(
+            overrides.find(&a, &dependency("c", "^1.0.0")),
+            Some("3.0.0")
+        );
+        assert_eq!(
+            overrides.find(&deep, &dependency("c", "^1.0.0")),
+            Some("3.0.0")
+        );
+        assert_eq!(overrides.find(&[], &dependency("c", "^1.0.0")), None);
+    }
+
+    #[test]
+    fn rejects_unknown_references() {
+        assert_eq!(
+            Overrides::from_manifest(&manifest(r#"{ "overrides": { "react": "$react" } }"#)),
+            Err(Error::InvalidOverride {
+                key: String::from("react"),
+                cause: String::from("the project doesn't depend on react"),
+            })
+        );
+    }
+}
@@ -0,0 +1,368 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use serde::Deserialize;
+use tar::{Builder, Header};
+
+use crate::Error;
+
+/// Never packed, whatever `files` or ignore files say
+const NEVER: [&str; 13] = [
+    ".npmignore",
+    ".gitignore",
+    ".git",
+    ".svn",
+    ".hg",
+    "CVS",
+    "node_modules",
+    ".npmrc",
+    "package-lock.json",
+    "yarn.lock",
+    "pnpm-lock.yaml",
+    "npm-debug.log",
+    ".DS_Store",
+];
+
+/// Always packed from the root of the package, along with package.json
+/// (compared case-insensitively, and with any extension)
+const ALWAYS: [&str; 4] = ["readme", "license", "licence", "changelog"];
+
+/// The date npm gives every file it packs, so packing the same files twice
+/// produces the same tarball
+const MTIME: u64 = 499162500;
+
+/// The parts of a package.json that decide what gets packed
+#[derive(Deserialize, Default)]
+struct PackManifest {
+    files: Option<Vec<String>>,
+    main: Option<String>,
+}
+
+/// Matches `name` against a glob where `*` is any run of characters and
+/// `?` any single one
+fn glob(pattern: &str, name: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let name: Vec<char> = name.chars().collect();
+    let (mut p, mut n) = (0, 0);
+    let mut backtrack: Option<(usize, usize)> = None;
+
+    while n < name.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                backtrack = Some((p, n));
+                p += 1;
+            }
+            Some(c) if *c == '?' || *c == name[n] => {
+                p += 1;
+                n += 1;
+            }
+            _ => match backtrack {
+                Some((star, matched)) => {
+                    p = star + 1;
+                    n = matched + 1;
+                    backtrack = Some((star, matched + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|c| *c == '*')
+}
+
+/// A pattern of a `.npmignore` or `.gitignore`
+struct Rule {
+    pattern: String,
+    negated: bool,
+    directory_only: bool,
+    /// Patterns with a slash match paths from the root, others match names
+    /// at any depth
+    anchored: bool,
+}
+
+impl Rule {
+    fn parse(line: &str) -> Option<Self> {
+        let line = line.trim_end();
+        if line.is_empty() || line.starts_with('#') {
+            return None;
+        }
+        let (negated, line) = match line.strip_prefix('!') {
+            Some(line) => (true, line),
+            None => (false, line),
+        };
+        let (directory_only, line) = match line.strip_suffix('/') {
+            Some(line) => (true, line),
+            None => (false, line),
+        };
+
+        Some(Self {
+            anchored: line.contains('/'),
+            pattern: line.trim_start_matches('/').to_string(),
+            negated,
+            directory_only,
+        })
+    }
+
+    fn matches(&self, path: &str, is_dir: bool) -> bool {
+        if self.directory_only && !is_dir {
+            return false;
+        }
+        if self.anchored {
+            glob(&self.pattern, path)
+        } else {
+            glob(&self.pattern, path.rsplit('/').next().unwrap_or(path))
+        }
+    }
+}
+
+/// Whether `path` (relative to the package, with `/` separators) is ignored.
+/// Like git, the last matching rule wins
+fn is_ignored(rules: &[Rule], path: &str, is_dir: bool) -> bool {
+    rules
+        .iter()
+        .rev()
+        .find(|rule| rule.matches(path, is_dir))
+        .is_some_and(|rule| !rule.negated)
+}
+
+/// Whether `path` is selected by a `files` entry: the entry itself, or
+/// anything inside a directory it names
+fn is_listed(files: &[String], path: &str) -> bool {
+    files.iter().any(|entry| {
+        let entry = entry.trim_start_matches("./").trim_end_matches('/');
+        let mut prefix = String::new();
+        path.split('/').any(|part| {
+            if !prefix.is_empty() {
+                prefix.push('/');
+            }
+            prefix.push_str(part);
+            glob(entry, &prefix)
+        })
+    })
+}
+
+fn is_always_packed(path: &str) -> bool {
+    if path.contains('/') {
+        return false;
+    }
+    let name = path.to_lowercase();
+    let stem = name.split('.').next().unwrap_or(&name);
+    name == "package.json" || ALWAYS.contains(&stem)
+}
+
+/// Walks `dir`, collecting every file that isn't ignored
+fn walk(
+    root: &Path,
+    dir: &Path,
+    rules: &[Rule],
+    files: &mut Vec<(String, PathBuf)>,
+) -> Result<(), Error> {
+    let mut entries = fs::read_dir(dir)
+        .map_err(|_| Error::IoError)?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|_| Error::IoError)?;
+    entries.sort_by_key(|entry| entry.file_name());
+
+    for entry in entries {
+        let path = entry.path();
+        let name = entry.file_name().to_string_lossy().to_string();
+        if NEVER.contains(&name.as_str()) {
+            continue;
+        }
+
+        let relative = path
+            .strip_prefix(root)
+            .map_err(|_| Error::IoError)?
+            .components()
+            .map(|part| part.as_os_str().to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/");
+        // Symlinks are followed, like npm does
+        let is_dir = path.is_dir();
+        if is_ignored(rules, &relative, is_dir) {
+            continue;
+        }
+
+        if is_dir {
+            walk(root, &path, rules, files)?;
+        } else if path.is_file() {
+            files.push((relative, path));
+        }
+    }
+    Ok(())
+}
+
+/// The files npm would pack from the package in `dir`, as paths relative to
+/// it. `files` in package.json selects what's packed when it's there,
+/// `.npmignore` (or `.gitignore` without one) what isn't otherwise
+pub(crate) fn packlist(dir: &Path) -> Result<Vec<(String, PathBuf)>, Error> {
+    let manifest: PackManifest = fs::read_to_string(dir.join("package.json"))
+        .ok()
+        .and_then(|contents| serde_json::from_str(&contents).ok())
+        .unwrap_or_default();
+
+    let ignore = [".npmignore", ".gitignore"]
+        .iter()
+        .find_map(|name| fs::read_to_string(dir.join(name)).ok())
+        .unwrap_or_default();
+    let rules: Vec<Rule> = match &manifest.files {
+        // The root ignore file doesn't apply to what `files` selects
+        Some(_) => Vec::new(),
+        None => ignore.lines().filter_map(Rule::parse).collect(),
+    };
+
+    let mut files = Vec::new();
+    walk(dir, dir, &rules, &mut files)?;
+
+    if let Some(listed) = &manifest.files {
+        let main = manifest
+            .main
+            .as_deref()
+            .map(|main| main.trim_start_matches("./"));
+        files.retain(|(path, _)| {
+            is_listed(listed, path) || is_always_packed(path) || Some(path.as_str()) == main
+        });
+    }
+    Ok(files)
+}
+
+#[cfg(unix)]
+fn is_executable(path: &Path) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+    fs::metadata(path).is_ok_and(|metadata| metadata.permissions().mode() & 0o111 != 0)
+}
+
+#[cfg(not(unix))]
+fn is_executable(_: &Path) -> bool {
+    false
+}
+
+/// Packs the package in `dir` into a tarball like `npm pack` does, with
+/// every file under `package/`
+pub fn pack(dir: &Path) -> Result<Vec<u8>, Error> {
+    let mut builder = Builder::new(GzEncoder::new(Vec::new(), Compression::default()));
+    for (relative, path) in packlist(dir)? {
+        let contents = fs::read(&path).map_err(|_| Error::IoError)?;
+        let mut header = Header::new_gnu();
+        header.set_size(contents.len() as u64);
+        header.set_mode(if is_executable(&path) { 0o755 } else { 0o644 });
+        header.set_mtime(MTIME);
+        builder
+            .append_data(
+                &mut header,
+                format!("package/{relative}"),
+                contents.as_slice(),
+            )
+            .map_err(|_| Error::IoError)?;
+    }
+
+    builder
+        .into_inner()
+        .and_then(|encoder| encoder.finish())
+        .map_err(|_| Error::IoError)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::extract::files;
+    use tempdir::TempDir;
+
+    fn package(files: &[(&str, &str)]) -> TempDir {
+        let dir = TempDir::new("vortex-pack").unwrap();
+        for (path, contents) in files {
+            let path = dir.path().join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, contents).unwrap();
+        }
+        dir
+    }
+
+    fn paths(dir: &TempDir) -> Vec<String> {
+        packlist(dir.path())
+            .unwrap()
+            .into_iter()
+            .map(|(path, _)| path)
+            .collect()
+    }
+
+    #[test]
+    fn matches_globs() {
+        assert!(glob("*.log", "debug.log"));
+        assert!(glob("a?c", "abc"));
+        assert!(glob("lib/*.js", "lib/index.js"));
+        assert!(!glob("*.log", "debug.txt"));
+        assert!(!glob("a?c", "ac"));
+    }
+
+    #[test]
+    fn honors_ignore_files() {
+        let dir = package(&[
+            ("package.json", "{}"),
+            (".npmignore", "*.log\n/test/\n!keep.log"),
+            (".gitignore", "lib/"),
+            ("lib/index.js", ""),
+            ("test/index.js", ""),
+            ("src/test/index.js", ""),
+            ("debug.log", ""),
+            ("keep.log", ""),
+            ("node_modules/a/index.js", ""),
+            (".git/HEAD", ""),
+        ]);
+
+        assert_eq!(
+            paths(&dir),
+            vec![
+                "keep.log",
+                "lib/index.js",
+                "package.json",
+                "src/test/index.js"
+            ]
+        );
+    }
+
+    #[test]
+    fn only_packs_listed_files() {
+        let dir = package(&[
+            (
+                "package.json",
+                r#"{"files": ["dist", "bin/*.js"], "main": "index.js"}"#,
+            ),
+            ("README.md", ""),
+            ("index.js", ""),
+            ("dist/a/b.js", ""),
+            ("bin/cli.js", ""),
+            ("bin/cli.ts", ""),
+            ("src/a.ts", ""),
+        ]);
+
+        assert_eq!(
+            paths(&dir),
+            vec![
+                "README.md",
+                "bin/cli.js",
+                "dist/a/b.js",
+                "index.js",
+                "package.json"
+            ]
+        );
+    }
+
+    #[test]
+    fn packs_under_package() {
+        let dir = package(&[("package.json", "{}"), ("lib/index.js", "1")]);
+
+        let tarball = pack(dir.path()).unwrap();
+
+        assert_eq!(tarball, pack(dir.path()).unwrap());
+        let packed = files(&tarball).unwrap();
+        assert_eq!(
+            packed
+                .iter()
+                .map(|file| file.path.clone())
+                .collect::<Vec<_>>(),
+            vec![PathBuf::from("lib/index.js"), PathBuf::from("package.json")]
+        );
+    }
+}
@@ -0,0 +1,759 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Component, Path, PathBuf};
+
+use serde::de::DeserializeOwned;
+use serde_json::{Map, Value};
+
+use crate::{
+    dist_integrity, git, DependencyKind, Dist, Error, Graph, Manifest, Metadata, PackageId,
+    Platform,
+};
+
+/// The lockfile npm keeps next to package.json
+pub const PACKAGE_LOCK_NAME: &str = "package-lock.json";
+
+/// npm's `package-lock.json`, lockfileVersion 2 or 3.
+///
+/// The file is kept as a JSON document rather than parsed into structs, so
+/// everything vortex doesn't know about (and the order of every key)
+/// survives an update. Entries whose package didn't change are copied over
+/// untouched, and a lockfile that didn't change at all is never rewritten
+#[derive(PartialEq, Debug, Clone)]
+pub struct PackageLock {
+    original: Value,
+    value: Value,
+}
+
+/// How an installed package is used, mirroring npm's `dev`, `optional`,
+/// `devOptional` and `peer` flags
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub(crate) struct Flags {
+    pub(crate) dev: bool,
+    pub(crate) optional: bool,
+    pub(crate) dev_optional: bool,
+    pub(crate) peer: bool,
+}
+
+impl Flags {
+    pub(crate) fn of(graph: &Graph) -> HashMap<&PackageId, Flags> {
+        let prod = graph.reachable(&[DependencyKind::Dev, DependencyKind::Optional]);
+        let without_dev = graph.reachable(&[DependencyKind::Dev]);
+        let without_optional = graph.reachable(&[DependencyKind::Optional]);
+        let without_peer = graph.reachable(&[DependencyKind::Peer]);
+
+        graph
+            .nodes()
+            .map(|(id, _)| {
+                let dev = !without_dev.contains(id);
+                let optional = !without_optional.contains(id);
+                let flags = Flags {
+                    dev,
+                    optional,
+                    dev_optional: !dev && !optional && !prod.contains(id),
+                    peer: !without_peer.contains(id),
+                };
+                (id, flags)
+            })
+            .collect()
+    }
+
+    fn fields(&self) -> [(&'static str, bool); 4] {
+        [
+            ("dev", self.dev),
+            ("optional", self.optional),
+            ("devOptional", self.dev_optional),
+            ("peer", self.peer),
+        ]
+    }
+}
+
+/// The name of the package installed at `key`, e.g. `@scope/b` for
+/// `node_modules/a/node_modules/@scope/b`
+fn name_from_key(key: &str) -> Option<&str> {
+    key.rsplit_once("node_modules/").map(|(_, name)| name)
+}
+
+/// `node_modules/a/node_modules/b`, with forward slashes on every platform
+fn key_from_path(path: &Path) -> String {
+    path.components()
+        .filter_map(|component| match component {
+            Component::Normal(part) => part.to_str(),
+            _ => None,
+        })
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// A field of a `packages` entry, if it's there and well-formed
+fn parse_field<T: DeserializeOwned>(entry: &Value, field: &str) -> Option<T> {
+    serde_json::from_value(entry.get(field)?.clone()).ok()
+}
+
+fn string_map<'a>(entries: impl Iterator<Item = (&'a String, &'a String)>) -> Value {
+    let sorted: BTreeMap<&String, &String> = entries.collect();
+    Value::Object(
+        sorted
+            .into_iter()
+            .map(|(name, range)| (name.clone(), Value::String(range.clone())))
+            .collect(),
+    )
+}
+
+/// Sets `key` to `value`, or removes it when `value` is `None`, keeping
+/// the position of every other key
+fn set_field(object: &mut Map<String, Value>, key: &str, value: Option<Value>) {
+    match value {
+        Some(value) if object.get(key) != Some(&value) => {
+            object.insert(key.to_string(), value);
+        }
+        Some(_) => {}
+        None if object.contains_key(key) => {
+            *object = std::mem::take(object)
+                .into_iter()
+                .filter(|(k, _)| k != key)
+                .collect();
+        }
+        None => {}
+    }
+}
+
+/// The package a `packages` entry locks, unless it's a link or has no
+/// tarball
+fn entry_metadata(key: &str, entry: &Value) -> Option<Metadata> {
+    if entry.get("link").and_then(Value::as_bool) == Some(true) {
+        return None;
+    }
+    let name = match entry.get("name").and_then(Value::as_str) {
+        Some(name) => name,
+        None => name_from_key(key)?,
+    };
+
+    let mut dependencies: HashMap<String, String> = HashMap::new();
+    for field in ["dependencies", "optionalDependencies"] {
+        for (name, range) in entry
+            .get(field)
+            .and_then(Value::as_object)
+            .into_iter()
+            .flatten()
+        {
+            if let Some(range) = range.as_str() {
+                dependencies.insert(name.clone(), range.to_string());
+            }
+        }
+    }
+
+    Some(Metadata {
+        name: name.to_string(),
+        version: entry.get("version")?.as_str()?.to_string(),
+        dependencies: Some(dependencies),
+        peer_dependencies: parse_field(entry, "peerDependencies"),
+        peer_dependencies_meta: parse_field(entry, "peerDependenciesMeta"),
+        optional_dependencies: parse_field(entry, "optionalDependencies"),
+        platform: Platform {
+            os: parse_field(entry, "os"),
+            cpu: parse_field(entry, "cpu"),
+            libc: parse_field(entry, "libc"),
+        },
+        dist: Dist {
+            tarball: entry.get("resolved")?.as_str()?.to_string(),
+            shasum: String::new(),
+            integrity: entry
+                .get("integrity")
+                .and_then(Value::as_str)
+                .map(String::from),
+        },
+    })
+}
+
+/// The entry Node finds `name` at when the package installed at `key`
+/// requires it
+fn find_installed<'a>(
+    packages: &'a Map<String, Value>,
+    key: &str,
+    name: &str,
+) -> Option<(&'a String, &'a Value)> {
+    let mut dir = key;
+    loop {
+        let candidate = if dir.is_empty() {
+            format!("node_modules/{name}")
+        } else {
+            format!("{dir}/node_modules/{name}")
+        };
+        if let Some(found) = packages.get_key_value(&candidate) {
+            return Some(found);
+        }
+        if dir.is_empty() {
+            return None;
+        }
+        dir = dir
+            .rsplit_once("/node_modules/")
+            .map_or("", |(parent, _)| parent);
+    }
+}
+
+impl PackageLock {
+    pub fn parse(contents: &str) -> Result<Self, Error> {
+        let value: Value = serde_json::from_str(contents).map_err(|_| Error::InvalidLockfile)?;
+
+        match value.get("lockfileVersion").and_then(Value::as_u64) {
+            Some(2 | 3) if value.get("packages").is_some_and(Value::is_object) => Ok(Self {
+                original: value.clone(),
+                value,
+            }),
+            _ => Err(Error::InvalidLockfile),
+        }
+    }
+
+    /// Reads a package-lock.json, returning `None` if it doesn't exist
+    pub fn read(path: &Path) -> Result<Option<Self>, Error> {
+        match fs::read_to_string(path) {
+            Ok(contents) => Self::parse(&contents).map(Some),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+            Err(_) => Err(Error::IoError),
+        }
+    }
+
+    pub fn lockfile_version(&self) -> u64 {
+        self.value["lockfileVersion"].as_u64().unwrap_or_default()
+    }
+
+    fn packages(&self) -> &Map<String, Value> {
+        self.value["packages"].as_object().unwrap()
+    }
+
+    /// Every package installed from the registry, for seeding a
+    /// [crate::Resolver]. Links and entries without a tarball are skipped
+    pub fn locked_packages(&self) -> Vec<Metadata> {
+        self.packages()
+            .iter()
+            .filter_map(|(key, entry)| entry_metadata(key, entry))
+            .collect()
+    }
+
+    /// The git packages locked for each `(name, spec)` dependency of the
+    /// project and its packages, for [crate::Resolver::with_locked_git]
+    pub fn locked_git(&self) -> HashMap<(String, String), Metadata> {
+        let packages = self.packages();
+        let mut locked = HashMap::new();
+        for (key, entry) in packages {
+            for field in ["dependencies", "devDependencies", "optionalDependencies"] {
+                let dependencies = entry.get(field).and_then(Value::as_object);
+                for (name, spec) in dependencies.into_iter().flatten() {
+                    let metadata = find_installed(packages, key, name)
+                        .and_then(|(key, entry)| entry_metadata(key, entry));
+                    if let (Some(spec), Some(metadata)) = (spec.as_str(), metadata) {
+                        if git::parse_resolved(&metadata.name, &metadata.dist.tarball).is_some() {
+                            locked.insert((name.clone(), spec.to_string()), metadata);
+                        }
+                    }
+                }
+            }
+        }
+        locked
+    }
+
+    /// Brings the lockfile in line with `manifest` and the packages
+    /// installed at `layout` (as returned by [crate::hoist])
+    pub fn update(
+        &mut self,
+        manifest: &Manifest,
+        graph: &Graph,
+        layout: &BTreeMap<PathBuf, PackageId>,
+    ) {
+        let flags = Flags::of(graph);
+        let original = self.packages();
+        let mut packages = Map::new();
+
+        let mut root = match original.get("") {
+            Some(Value::Object(root)) => root.clone(),
+            _ => Map::new(),
+        };
+        for (field, value) in [("name", &manifest.name), ("version", &manifest.version)] {
+            set_field(&mut root, field, value.clone().map(Value::String));
+        }
+        for (field, dependencies) in [
+            ("dependencies", &manifest.dependencies),
+            ("devDependencies", &manifest.dev_dependencies),
+            ("optionalDependencies", &manifest.optional_dependencies),
+            ("peerDependencies", &manifest.peer_dependencies),
+        ] {
+            let value = dependencies.as_ref().map(|deps| string_map(deps.iter()));
+            set_field(&mut root, field, value);
+        }
+        packages.insert(String::new(), Value::Object(root));
+
+        // Workspaces and other packages outside node_modules aren't
+        // resolved by vortex, so they're kept as they are
+        for (key, entry) in original {
+            if !key.is_empty()
+                && !key.starts_with("node_modules/")
+                && !key.contains("/node_modules/")
+            {
+                packages.insert(key.clone(), entry.clone());
+            }
+        }
+
+        let installed: BTreeMap<String, &PackageId> = layout
+            .iter()
+            .map(|(path, id)| (key_from_path(path), id))
+            .collect();
+        for (key, id) in installed {
+            let node = match graph.node(id) {
+                Some(node) => node,
+                None => continue,
+            };
+            let flags = flags[id];
+
+            let mut entry = match original.get(&key) {
+                Some(Value::Object(entry))
+                    if entry.get("version").and_then(Value::as_str) == Some(&id.version) =>
+                {
+                    entry.clone()
+                }
+                _ => {
+                    let mut entry = Map::new();
+                    if name_from_key(&key) != Some(&id.name) {
+                        entry.insert(String::from("name"), Value::String(id.name.clone()));
+                    }
+                    entry.insert(String::from("version"), Value::String(id.version.clone()));
+                    entry.insert(
+                        String::from("resolved"),
+                        Value::String(node.metadata.dist.tarball.clone()),
+                    );
+                    if let Some(integrity) = dist_integrity(&node.metadata.dist) {
+                        entry.insert(String::from("integrity"), Value::String(integrity));
+                    }
+                    // Registry manifests list optional dependencies in both
+                    // maps, npm only under `optionalDependencies`
+                    let optional = node.metadata.optional_dependencies.iter().flatten();
+                    let dependencies = node
+                        .metadata
+                        .dependencies
+                        .iter()
+                        .flatten()
+                        .filter(|(name, _)| optional.clone().all(|(other, _)| other != *name));
+                    if dependencies.clone().next().is_some() {
+                        entry.insert(String::from("dependencies"), string_map(dependencies));
+                    }
+                    if optional.clone().next().is_some() {
+                        entry.insert(String::from("optionalDependencies"), string_map(optional));
+                    }
+                    let peers = node.metadata.peer_dependencies.iter().flatten();
+                    if peers.clone().next().is_some() {
+                        entry.insert(String::from("peerDependencies"), string_map(peers));
+                    }
+                    entry
+                }
+            };
+
+            if flags.fields().iter().any(|(field, set)| {
+                entry.get(*field).and_then(Value::as_bool).unwrap_or(false) != *set
+            }) {
+                entry = with_flags(entry, flags);
+            }
+            packages.insert(key, Value::Object(entry));
+        }
+
+        let packages = Value::Object(packages);
+        if self.value["packages"] == packages {
+            return;
+        }
+        self.value["packages"] = packages;
+        if self.lockfile_version() == 2 {
+            self.value["dependencies"] = legacy_dependencies(self.packages());
+        }
+    }
+
+    /// Whether [PackageLock::update] changed anything since the file was read
+    pub fn is_changed(&self) -> bool {
+        self.value != self.original
+    }
+
+    /// The lockfile as npm formats it: two-space indentation and a trailing
+    /// newline
+    pub fn to_json(&self) -> String {
+        let mut contents = serde_json::to_string_pretty(&self.value).unwrap();
+        contents.push('\n');
+        contents
+    }
+
+    /// Writes the lockfile if it changed, leaving the file untouched otherwise
+    pub fn write(&self, path: &Path) -> Result<(), Error> {
+        if !self.is_changed() {
+            return Ok(());
+        }
+        fs::write(path, self.to_json()).map_err(|_| Error::IoError)
+    }
+}
+
+/// Replaces the flags of a `packages` entry, placing them after the
+/// fields identifying the package like npm does
+fn with_flags(entry: Map<String, Value>, flags: Flags) -> Map<String, Value> {
+    let fields = flags.fields();
+    let mut result = Map::new();
+    let mut pending = true;
+
+    for (key, value) in entry {
+        if fields.iter().any(|(field, _)| *field == key) {
+            continue;
+        }
+        let identifies = ["name", "version", "resolved", "integrity"].contains(&key.as_str());
+        if pending && !identifies {
+            insert_flags(&mut result, &fields);
+            pending = false;
+        }
+        result.insert(key, value);
+    }
+    if pending {
+        insert_flags(&mut result, &fields);
+    }
+
+    result
+}
+
+fn insert_flags(entry: &mut Map<String, Value>, fields: &[(&'static str, bool)]) {
+    for (field, set) in fields {
+        if *set {
+            entry.insert(field.to_string(), Value::Bool(true));
+        }
+    }
+}
+
+/// Rebuilds the nested `dependencies` tree lockfileVersion 2 keeps for
+/// npm 6 out of the `packages` map
+fn legacy_dependencies(packages: &Map<String, Value>) -> Value {
+    let mut tree = Map::new();
+    let mut keys: Vec<&String> = packages
+        .keys()
+        .filter(|key| key.starts_with("node_modules/"))
+        .collect();
+    keys.sort();
+
+    for key in keys {
+        let entry = &packages[key];
+        let names: Vec<&str> = key["node_modules/".len()..]
+            .split("/node_modules/")
+            .collect();
+
+        let mut legacy = Map::new();
+        for field in ["version", "resolved", "integrity", "dev", "optional"] {
+            if let Some(value) = entry.get(field) {
+                legacy.insert(field.to_string(), value.clone());
+            }
+        }
+        let mut requires = Map::new();
+        for field in ["dependencies", "optionalDependencies"] {
+            if let Some(Value::Object(dependencies)) = entry.get(field) {
+                requires.extend(dependencies.clone());
+            }
+        }
+        if !requires.is_empty() {
+            legacy.insert(String::from("requires"), Value::Object(requires));
+        }
+
+        // Keys are sorted, so every parent has been added before its children
+        insert_legacy(&mut tree, &names, legacy);
+    }
+
+    Value::Object(tree)
+}
+
+fn insert_legacy(level: &mut Map<String, Value>, names: &[&str], entry: Map<String, Value>) {
+    match names {
+        [name] => {
+            level.insert(name.to_string(), Value::Object(entry));
+        }
+        [parent, rest @ ..] => {
+            if let Some(Value::Object(parent)) = level.get_mut(*parent) {
+                let children = parent
+                    .entry("dependencies")
+                    .or_insert_with(|| Value::Object(Map::new()));
+                if let Value::Object(children) = children {
+                    insert_legacy(children, rest, entry);
+                }
+            }
+        }
+        [] => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::metadata;
+    use crate::{hoist, Edge};
+
+    const LOCK: &str = r#"{
+  "name": "app",
+  "version": "1.0.0",
+  "lockfileVersion": 3,
+  "requires": true,
+  "packages": {
+    "": {
+      "name": "app",
+      "version": "1.0.0",
+      "license": "MIT",
+      "dependencies": {
+        "a": "^1.0.0"
+      },
+      "devDependencies": {
+        "@types/b": "^2.0.0"
+      }
+    },
+    "node_modules/@types/b": {
+      "version": "2.0.0",
+      "resolved": "https://registry.npmjs.org/@types/b/-/b-2.0.0.tgz",
+      "integrity": "sha512-bbbb",
+      "dev": true,
+      "license": "MIT"
+    },
+    "node_modules/a": {
+      "version": "1.0.0",
+      "resolved": "https://registry.npmjs.org/a/-/a-1.0.0.tgz",
+      "integrity": "sha512-aaaa",
+      "license": "MIT",
+      "dependencies": {
+        "@types/b": "^1.0.0"
+      }
+    },
+    "node_modules/a/node_modules/@types/b": {
+      "version": "1.0.0",
+      "resolved": "https://registry.npmjs.org/@types/b/-/b-1.0.0.tgz",
+      "integrity": "sha512-cccc",
+      "license": "MIT"
+    }
+  }
+}
+"#;
+
+    fn manifest() -> Manifest {
+        serde_json::from_str(
+            r#"{
+                "name": "app",
+                "version": "1.0.0",
+                "dependencies": { "a": "^1.0.0" },
+                "devDependencies": { "@types/b": "^2.0.0" }
+            }"#,
+        )
+        .unwrap()
+    }
+
+    fn edge(name: &str, range: &str, version: &str, kind: DependencyKind) -> Edge {
+        Edge {
+            name: name.to_string(),
+            range: range.to_string(),
+            kind,
+            to: PackageId::new(name, version),
+            overridden: None,
+        }
+    }
+
+    /// The graph `LOCK` was resolved from, with `a` depending on
+    /// `@types/b@nested`
+    fn graph(nested: &str) -> Graph {
+        let mut graph = Graph::new();
+        let a = PackageId::new("a", "1.0.0");
+        graph.add_node(metadata("a", "1.0.0", &[("@types/b", "^1.0.0")]));
+        graph.add_node(metadata("@types/b", nested, &[]));
+        graph.add_node(metadata("@types/b", "2.0.0", &[]));
+        graph.add_edge(None, edge("a", "^1.0.0", "1.0.0", DependencyKind::Prod));
+        graph.add_edge(
+            None,
+            edge("@types/b", "^2.0.0", "2.0.0", DependencyKind::Dev),
+        );
+        graph.add_edge(
+            Some(&a),
+            edge("@types/b", "^1.0.0", nested, DependencyKind::Prod),
+        );
+        graph
+    }
+
+    #[test]
+    fn reads_locked_packages() {
+        let lock = PackageLock::parse(LOCK).unwrap();
+        let mut packages: Vec<(String, String, Option<String>)> = lock
+            .locked_packages()
+            .into_iter()
+            .map(|metadata| (metadata.name, metadata.version, metadata.dist.integrity))
+            .collect();
+        packages.sort();
+
+        assert_eq!(
+            packages,
+            vec![
+                (s("@types/b"), s("1.0.0"), Some(s("sha512-cccc"))),
+                (s("@types/b"), s("2.0.0"), Some(s("sha512-bbbb"))),
+                (s("a"), s("1.0.0"), Some(s("sha512-aaaa"))),
+            ]
+        );
+    }
+
+    #[test]
+    fn reads_locked_git_packages() {
+        let lock = PackageLock::parse(
+            r#"{
+  "lockfileVersion": 3,
+  "packages": {
+    "": {
+      "dependencies": { "a": "^1.0.0", "g": "github:user/g#main" }
+    },
+    "node_modules/a": {
+      "version": "1.0.0",
+      "resolved": "https://registry.npmjs.org/a/-/a-1.0.0.tgz",
+      "dependencies": { "g": "github:user/g#semver:^1.0.0" }
+    },
+    "node_modules/a/node_modules/g": {
+      "version": "1.0.0",
+      "resolved": "git+ssh://git@github.com/user/g.git#1111111111111111111111111111111111111111"
+    },
+    "node_modules/g": {
+      "version": "2.0.0",
+      "resolved": "git+ssh://git@github.com/user/g.git#2222222222222222222222222222222222222222"
+    }
+  }
+}"#,
+        )
+        .unwrap();
+        let mut locked: Vec<(String, String)> = lock
+            .locked_git()
+            .into_iter()
+            .map(|((_, spec), metadata)| (spec, metadata.version))
+            .collect();
+        locked.sort();
+
+        assert_eq!(
+            locked,
+            vec![
+                (s("github:user/g#main"), s("2.0.0")),
+                (s("github:user/g#semver:^1.0.0"), s("1.0.0")),
+            ]
+        );
+    }
+
+    fn s(value: &str) -> String {
+        value.to_string()
+    }
+
+    #[test]
+    fn unchanged_lockfile_is_byte_identical() {
+        let mut lock = PackageLock::parse(LOCK).unwrap();
+        let graph = graph("1.0.0");
+        lock.update(&manifest(), &graph, &hoist(&graph));
+
+        assert!(!lock.is_changed());
+        assert_eq!(lock.to_json(), LOCK);
+    }
+
+    #[test]
+    fn updates_changed_packages() {
+        let mut lock = PackageLock::parse(LOCK).unwrap();
+        let mut manifest = manifest();
+        manifest.dev_dependencies = None;
+
+        let mut graph = Graph::new();
+        graph.add_node(metadata("a", "1.1.0", &[]));
+        graph.add_edge(None, edge("a", "^1.0.0", "1.1.0", DependencyKind::Prod));
+        lock.update(&manifest, &graph, &hoist(&graph));
+
+        assert!(lock.is_changed());
+        assert_eq!(
+            lock.value["packages"],
+            serde_json::json!({
+                "": {
+                    "name": "app",
+                    "version": "1.0.0",
+                    "license": "MIT",
+                    "dependencies": { "a": "^1.0.0" }
+                },
+                "node_modules/a": {
+                    "version": "1.1.0",
+                    "resolved": "https://registry.npmjs.org/a/-/a-1.1.0.tgz"
+                }
+            })
+        );
+    }
+
+    #[test]
+    fn writes_optional_dependencies_apart() {
+        let mut lock = PackageLock::parse(LOCK).unwrap();
+        let mut manifest = manifest();
+        manifest.dev_dependencies = None;
+
+        let mut graph = Graph::new();
+        let a = PackageId::new("a", "1.1.0");
+        let mut metadata_a = metadata("a", "1.1.0", &[("c", "^1.0.0"), ("d", "^1.0.0")]);
+        metadata_a.optional_dependencies = Some(HashMap::from([(s("d"), s("^1.0.0"))]));
+        graph.add_node(metadata_a.clone());
+        graph.add_node(metadata("c", "1.0.0", &[]));
+        graph.add_node(metadata("d", "1.0.0", &[]));
+        graph.add_edge(None, edge("a", "^1.0.0", "1.1.0", DependencyKind::Prod));
+        graph.add_edge(Some(&a), edge("c", "^1.0.0", "1.0.0", DependencyKind::Prod));
+        graph.add_edge(
+            Some(&a),
+            edge("d", "^1.0.0", "1.0.0", DependencyKind::Optional),
+        );
+        lock.update(&manifest, &graph, &hoist(&graph));
+
+        assert_eq!(
+            lock.value["packages"]["node_modules/a"],
+            serde_json::json!({
+                "version": "1.1.0",
+                "resolved": "https://registry.npmjs.org/a/-/a-1.1.0.tgz",
+                "dependencies": { "c": "^1.0.0" },
+                "optionalDependencies": { "d": "^1.0.0" }
+            })
+        );
+        assert_eq!(lock.value["packages"]["node_modules/d"]["optional"], true);
+        let read = PackageLock::parse(&lock.to_json())
+            .unwrap()
+            .locked_packages()
+            .into_iter()
+            .find(|metadata| metadata.name == "a")
+            .unwrap();
+        assert_eq!(read.optional_dependencies, metadata_a.optional_dependencies);
+        assert_eq!(read.dependencies, metadata_a.dependencies);
+    }
+
+    #[test]
+    fn rebuilds_legacy_dependencies() {
+        let mut lock =
+            PackageLock::parse(&LOCK.replace("\"lockfileVersion\": 3", "\"lockfileVersion\": 2"))
+                .unwrap();
+        let graph = graph("1.0.1");
+        lock.update(&manifest(), &graph, &hoist(&graph));
+
+        assert_eq!(
+            lock.value["dependencies"],
+            serde_json::json!({
+                "@types/b": {
+                    "version": "2.0.0",
+                    "resolved": "https://registry.npmjs.org/@types/b/-/b-2.0.0.tgz",
+                    "integrity": "sha512-bbbb",
+                    "dev": true
+                },
+                "a": {
+                    "version": "1.0.0",
+                    "resolved": "https://registry.npmjs.org/a/-/a-1.0.0.tgz",
+                    "integrity": "sha512-aaaa",
+                    "requires": { "@types/b": "^1.0.0" },
+                    "dependencies": {
+                        "@types/b": {
+                            "version": "1.0.1",
+                            "resolved": "https://registry.npmjs.org/@types/b/-/b-1.0.1.tgz"
+                        }
+                    }
+                }
+            })
+        );
+    }
+
+    #[test]
+    fn rejects_old_lockfiles() {
+        assert_eq!(
+            PackageLock::parse(r#"{ "lockfileVersion": 1, "dependencies": {} }"#),
+            Err(Error::InvalidLockfile)
+        );
+    }
+}
@@ -0,0 +1,216 @@
+use std::collections::{HashMap, VecDeque};
+use std::fmt::{self, Display};
+
+use node_semver::{Range, Version};
+
+use crate::{DependencyKind, Edge, Graph, PackageId};
+
+/// A peer dependency resolved to a version outside of its range, because
+/// whatever provides it wants another version, or to a different version
+/// than one of the package's dependents provides
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub struct PeerConflict {
+    /// The packages from the project down to the one with the peer
+    /// dependency
+    pub path: Vec<PackageId>,
+    pub name: String,
+    pub range: String,
+    pub found: PackageId,
+    /// What the dependent at the end of `path` provides instead, when
+    /// that's not `found`. A package is only installed once, so it shares
+    /// its peers with every dependent but the first
+    pub provided: Option<PackageId>,
+}
+
+impl Display for PeerConflict {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let path = self
+            .path
+            .iter()
+            .map(PackageId::to_string)
+            .collect::<Vec<_>>()
+            .join(" > ");
+        match &self.provided {
+            Some(provided) => {
+                let dependent = match self.path.iter().rev().nth(1) {
+                    Some(dependent) => dependent.to_string(),
+                    None => String::from("the project"),
+                };
+                write!(
+                    f,
+                    "{path} wants {}@{} as a peer, and {dependent} provides {}, but it gets {}",
+                    self.name, self.range, provided, self.found
+                )
+            }
+            None => write!(
+                f,
+                "{path} wants {}@{} as a peer, but gets {}",
+                self.name, self.range, self.found
+            ),
+        }
+    }
+}
+
+fn satisfies(edge: &Edge) -> bool {
+    match (Range::parse(&edge.range), Version::parse(&edge.to.version)) {
+        (Ok(range), Ok(version)) => range.satisfies(&version),
+        // Tags, URLs and paths can't be checked
+        _ => true,
+    }
+}
+
+/// The packages from the project down to `id`, the way `parents` first
+/// reached it
+fn path_to(parents: &HashMap<&PackageId, Option<&PackageId>>, id: &PackageId) -> Vec<PackageId> {
+    let mut path = vec![id.clone()];
+    let mut parent = parents.get(id).copied().flatten();
+    while let Some(id) = parent {
+        path.push(id.clone());
+        parent = parents.get(id).copied().flatten();
+    }
+    path.reverse();
+    path
+}
+
+/// What a dependent of a package provides it as `name`: its own
+/// dependency, or failing that the project's
+fn provided<'a>(
+    graph: &'a Graph,
+    dependent: Option<&PackageId>,
+    name: &str,
+) -> Option<&'a PackageId> {
+    let own = dependent
+        .and_then(|dependent| graph.node(dependent))
+        .and_then(|node| node.edges.iter().find(|edge| edge.name == name));
+    own.or_else(|| graph.roots().iter().find(|edge| edge.name == name))
+        .map(|edge| &edge.to)
+}
+
+/// Every peer dependency of `graph` whose range isn't satisfied, along with
+/// the shortest path that brought in the package depending on it, and
+/// every dependent providing a package another version of its peers than
+/// the one it was resolved with
+pub fn peer_conflicts(graph: &Graph) -> Vec<PeerConflict> {
+    // How each package was first reached from the project
+    let mut parents: HashMap<&PackageId, Option<&PackageId>> = HashMap::new();
+    let mut queue: VecDeque<&PackageId> = VecDeque::new();
+    for edge in graph.roots() {
+        if !parents.contains_key(&edge.to) {
+            parents.insert(&edge.to, None);
+            queue.push_back(&edge.to);
+        }
+    }
+
+    // Every path is needed before looking at the dependents of a package
+    let mut order = Vec::new();
+    while let Some(id) = queue.pop_front() {
+        order.push(id);
+        for edge in graph.node(id).into_iter().flat_map(|node| &node.edges) {
+            if !parents.contains_key(&edge.to) {
+                parents.insert(&edge.to, Some(id));
+                queue.push_back(&edge.to);
+            }
+        }
+    }
+
+    let mut conflicts = Vec::new();
+    for id in order {
+        let node = match graph.node(id) {
+            Some(node) => node,
+            None => continue,
+        };
+        let peers = node
+            .edges
+            .iter()
+            .filter(|edge| edge.kind == DependencyKind::Peer);
+        for edge in peers {
+            if !satisfies(edge) {
+                conflicts.push(PeerConflict {
+                    path: path_to(&parents, id),
+                    name: edge.name.clone(),
+                    range: edge.range.clone(),
+                    found: edge.to.clone(),
+                    provided: None,
+                });
+            }
+            for (dependent, _) in graph.dependents(id) {
+                let provided = match provided(graph, dependent, &edge.name) {
+                    Some(provided) if provided != &edge.to => provided,
+                    _ => continue,
+                };
+                let mut path = dependent
+                    .map(|dependent| path_to(&parents, dependent))
+                    .unwrap_or_default();
+                path.push(id.clone());
+                let conflict = PeerConflict {
+                    path,
+                    name: edge.name.clone(),
+                    range: edge.range.clone(),
+                    found: edge.to.clone(),
+                    provided: Some(provided.clone()),
+                };
+                if !conflicts.contains(&conflict) {
+                    conflicts.push(conflict);
+                }
+            }
+        }
+    }
+    conflicts
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::metadata;
+
+    fn edge(name: &str, range: &str, kind: DependencyKind, to: &PackageId) -> Edge {
+        Edge {
+            name: name.to_string(),
+            range: range.to_string(),
+            kind,
+            to: to.clone(),
+            overridden: None,
+        }
+    }
+
+    #[test]
+    fn reports_unsatisfied_peers_with_their_path() {
+        let app = PackageId::new("app", "1.0.0");
+        let plugin = PackageId::new("plugin", "1.0.0");
+        let react = PackageId::new("react", "18.2.0");
+        let mut graph = Graph::new();
+        graph.add_node(metadata("app", "1.0.0", &[]));
+        graph.add_node(metadata("plugin", "1.0.0", &[]));
+        graph.add_node(metadata("react", "18.2.0", &[]));
+        graph.add_edge(None, edge("app", "^1.0.0", DependencyKind::Prod, &app));
+        graph.add_edge(
+            Some(&app),
+            edge("plugin", "^1.0.0", DependencyKind::Prod, &plugin),
+        );
+        graph.add_edge(
+            Some(&app),
+            edge("react", "^18.0.0", DependencyKind::Prod, &react),
+        );
+        graph.add_edge(
+            Some(&plugin),
+            edge("react", "^17.0.0", DependencyKind::Peer, &react),
+        );
+
+        let conflicts = peer_conflicts(&graph);
+
+        assert_eq!(
+            conflicts,
+            vec![PeerConflict {
+                path: vec![app, plugin],
+                name: String::from("react"),
+                range: String::from("^17.0.0"),
+                found: react,
+                provided: None,
+            }]
+        );
+        assert_eq!(
+            conflicts[0].to_string(),
+            "app@1.0.0 > plugin@1.0.0 wants react@^17.0.0 as a peer, but gets react@18.2.0"
+        );
+    }
+}
@@ -0,0 +1,142 @@
+use std::fs;
+
+use serde::{Deserialize, Serialize};
+
+/// The `os`, `cpu` and `libc` fields of a package, which limit the
+/// platforms it can be installed on. Entries starting with `!` exclude a
+/// platform instead
+#[derive(Serialize, Deserialize, PartialEq, Eq, Debug, Clone, Default)]
+pub struct Platform {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub os: Option<Vec<String>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cpu: Option<Vec<String>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub libc: Option<Vec<String>>,
+}
+
+/// Whether `value` is allowed by a list of `os`, `cpu` or `libc` entries,
+/// the way npm checks them
+fn allows(entries: &Option<Vec<String>>, value: Option<&str>) -> bool {
+    let entries = match entries {
+        Some(entries) if !entries.is_empty() => entries,
+        _ => return true,
+    };
+    let value = match value {
+        Some(value) => value,
+        // Nothing to compare against, like libc on macOS
+        None => return true,
+    };
+
+    let mut allowed = false;
+    let mut has_positive = false;
+    for entry in entries {
+        match entry.strip_prefix('!') {
+            Some(excluded) if excluded == value => return false,
+            Some(_) => {}
+            None => {
+                has_positive = true;
+                allowed |= entry == value || entry == "any";
+            }
+        }
+    }
+    allowed || !has_positive
+}
+
+impl Platform {
+    /// Whether a package with these fields can be installed on `host`
+    pub fn supports(&self, host: &Host) -> bool {
+        allows(&self.os, Some(&host.os))
+            && allows(&self.cpu, Some(&host.cpu))
+            && allows(&self.libc, host.libc.as_deref())
+    }
+}
+
+/// The platform packages are installed for, named the way Node names them
+/// (`process.platform`, `process.arch` and glibc or musl)
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub struct Host {
+    pub os: String,
+    pub cpu: String,
+    /// Only known on Linux
+    pub libc: Option<String>,
+}
+
+impl Host {
+    /// The machine vortex is running on
+    pub fn current() -> Self {
+        let os = match std::env::consts::OS {
+            "macos" => "darwin",
+            "windows" => "win32",
+            "solaris" | "illumos" => "sunos",
+            os => os,
+        };
+        let cpu = match std::env::consts::ARCH {
+            "x86_64" => "x64",
+            "x86" => "ia32",
+            "aarch64" => "arm64",
+            "powerpc64" => "ppc64",
+            "loongarch64" => "loong64",
+            cpu => cpu,
+        };
+        let libc = (os == "linux").then(|| {
+            // musl's dynamic loader is the giveaway, like it is for npm
+            let musl = fs::read_dir("/lib")
+                .into_iter()
+                .flatten()
+                .flatten()
+                .any(|entry| entry.file_name().to_string_lossy().starts_with("ld-musl-"));
+            String::from(if musl { "musl" } else { "glibc" })
+        });
+
+        Self {
+            os: os.to_string(),
+            cpu: cpu.to_string(),
+            libc,
+        }
+    }
+}
+
+impl Default for Host {
+    fn default() -> Self {
+        Self::current()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn list(entries: &[&str]) -> Option<Vec<String>> {
+        Some(entries.iter().map(|entry| entry.to_string()).collect())
+    }
+
+    fn host(os: &str, cpu: &str, libc: Option<&str>) -> Host {
+        Host {
+            os: os.to_string(),
+            cpu: cpu.to_string(),
+            libc: libc.map(String::from),
+        }
+    }
+
+    #[test]
+    fn checks_platforms_like_npm() {
+        let linux = host("linux", "x64", Some("glibc"));
+        let platform = |os: &[&str], cpu: &[&str], libc: &[&str]| Platform {
+            os: list(os),
+            cpu: list(cpu),
+            libc: list(libc),
+        };
+
+        assert!(Platform::default().supports(&linux));
+        assert!(platform(&["linux"], &["x64", "arm64"], &["glibc"]).supports(&linux));
+        assert!(platform(&["!win32"], &[], &[]).supports(&linux));
+        assert!(platform(&["any"], &[], &[]).supports(&linux));
+        assert!(!platform(&["darwin"], &[], &[]).supports(&linux));
+        assert!(!platform(&["!linux"], &[], &[]).supports(&linux));
+        assert!(!platform(&[], &["arm64"], &[]).supports(&linux));
+        assert!(!platform(&["linux"], &["x64"], &["musl"]).supports(&linux));
+        // libc only means something on Linux
+        assert!(platform(&[], &[], &["musl"]).supports(&host("darwin", "arm64", None)));
+    }
+}
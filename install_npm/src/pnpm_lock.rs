@@ -0,0 +1,405 @@
+use std::collections::HashMap;
+
+use serde_yaml::{Mapping, Value};
+
+use crate::import::{split_key, unmappable, Pins};
+use crate::{
+    tarball_url, Dependency, Dist, Error, Import, Metadata, Npmrc, PackageId, Platform, Unmapped,
+};
+
+/// The lockfile of pnpm
+pub const PNPM_LOCK_NAME: &str = "pnpm-lock.yaml";
+
+fn get<'a>(value: &'a Value, key: &str) -> Option<&'a Value> {
+    value.as_mapping()?.get(key)
+}
+
+fn mapping(value: Option<&Value>) -> impl Iterator<Item = (&str, &Value)> {
+    value
+        .and_then(Value::as_mapping)
+        .map(Mapping::iter)
+        .into_iter()
+        .flatten()
+        .filter_map(|(key, value)| Some((key.as_str()?, value)))
+}
+
+/// The version a dependency was pinned to. pnpm 5 writes it directly,
+/// later versions next to the `specifier`
+fn pinned_version(value: &Value) -> Option<&str> {
+    match value {
+        Value::String(version) => Some(version),
+        Value::Mapping(_) => get(value, "version")?.as_str(),
+        _ => None,
+    }
+}
+
+/// `1.0.0` out of `1.0.0(react@18.0.0)` (pnpm 6+) or `1.0.0_react@18.0.0`
+/// (pnpm 5), which tell apart copies of a package with different peers.
+/// Specs that aren't versions, like `link:../my_package`, are left alone
+fn strip_peers(version: &str) -> &str {
+    match unmappable(version) {
+        Some(_) => version,
+        None => version.split(['(', '_']).next().unwrap_or(version),
+    }
+}
+
+/// The `(name, version)` of a `packages` key: `/@scope/a/1.0.0` in
+/// pnpm 5, `/@scope/a@1.0.0` in pnpm 6 and `@scope/a@1.0.0` in pnpm 9
+fn split_package_key(key: &str, major: u64) -> Option<(&str, &str)> {
+    let key = key.strip_prefix('/').unwrap_or(key);
+    if major < 6 {
+        key.rsplit_once('/')
+    } else {
+        split_key(key)
+    }
+}
+
+/// Why a package resolved to something other than the registry can't be
+/// imported, if it can't
+fn unmappable_resolution(resolution: Option<&Value>) -> Option<&'static str> {
+    let resolution = match resolution {
+        Some(resolution) => resolution,
+        None => return Some("no resolution"),
+    };
+    if get(resolution, "type").is_some() || get(resolution, "repo").is_some() {
+        Some("git dependency")
+    } else if get(resolution, "tarball").is_some() {
+        Some("tarball URL")
+    } else if get(resolution, "directory").is_some() {
+        Some("local package")
+    } else if get(resolution, "integrity").is_none() {
+        Some("no integrity")
+    } else {
+        None
+    }
+}
+
+/// Converts a `pnpm-lock.yaml` (lockfile versions 5, 6 and 9) into a
+/// [crate::Graph] rooted at `dependencies`. Only the project at the root of
+/// a workspace is imported.
+///
+/// pnpm records the version each dependency resolved to rather than its
+/// range, so that version is used as the range of the imported edges. It
+/// doesn't record tarball URLs either, so those point at the registry
+/// `npmrc` fetches each package from
+pub fn import_pnpm_lock(
+    contents: &str,
+    dependencies: &[Dependency],
+    npmrc: &Npmrc,
+) -> Result<Import, Error> {
+    let lock: Value = serde_yaml::from_str(contents).map_err(|_| Error::InvalidLockfile)?;
+    let major = match get(&lock, "lockfileVersion") {
+        Some(Value::Number(version)) => version.as_f64().map(|version| version as u64),
+        Some(Value::String(version)) => version
+            .split('.')
+            .next()
+            .and_then(|major| major.parse().ok()),
+        _ => None,
+    }
+    .ok_or(Error::InvalidLockfile)?;
+
+    // Workspaces (and pnpm 9) list the root project under `importers`
+    let root = get(&lock, "importers")
+        .and_then(|importers| get(importers, "."))
+        .unwrap_or(&lock);
+    let mut pinned: HashMap<&str, &str> = HashMap::new();
+    for map in ["dependencies", "devDependencies", "optionalDependencies"] {
+        for (name, value) in mapping(get(root, map)) {
+            if let Some(version) = pinned_version(value) {
+                pinned.insert(name, strip_peers(version));
+            }
+        }
+    }
+
+    // pnpm 9 keeps resolutions under `packages` and dependencies under
+    // `snapshots`, earlier versions both under `packages`
+    let packages = get(&lock, "packages");
+    let snapshots = if major >= 9 {
+        get(&lock, "snapshots")
+    } else {
+        packages
+    };
+
+    let mut pins = Pins::default();
+    for (key, snapshot) in mapping(snapshots) {
+        let (name, reference) = split_package_key(key, major).ok_or(Error::InvalidLockfile)?;
+        let version = strip_peers(reference);
+        let package = if major >= 9 {
+            packages.and_then(|packages| get(packages, &format!("{name}@{version}")))
+        } else {
+            Some(snapshot)
+        };
+
+        let resolution = package.and_then(|package| get(package, "resolution"));
+        if let Some(reason) = unmappable_resolution(resolution) {
+            pins.unmapped.push(Unmapped {
+                name: name.to_string(),
+                spec: reference.to_string(),
+                reason,
+            });
+            continue;
+        }
+
+        let dependencies = |map| -> HashMap<String, String> {
+            mapping(get(snapshot, map))
+                .filter_map(|(name, value)| {
+                    let version = strip_peers(value.as_str()?);
+                    Some((name.to_string(), version.to_string()))
+                })
+                .collect()
+        };
+
+        let metadata = Metadata {
+            name: name.to_string(),
+            version: version.to_string(),
+            dependencies: Some(dependencies("dependencies")),
+            peer_dependencies: None,
+            peer_dependencies_meta: None,
+            optional_dependencies: Some(dependencies("optionalDependencies")),
+            platform: Platform::default(),
+            dist: Dist {
+                tarball: tarball_url(npmrc.registry_for(name), name, version),
+                shasum: String::new(),
+                integrity: resolution
+                    .and_then(|resolution| get(resolution, "integrity"))
+                    .and_then(Value::as_str)
+                    .map(String::from),
+            },
+        };
+        let id = PackageId::from(&metadata);
+        pins.requests
+            .insert((name.to_string(), version.to_string()), id.clone());
+        pins.packages.entry(id).or_insert(metadata);
+    }
+
+    let mut roots: Vec<(Dependency, String)> = Vec::new();
+    for dependency in dependencies {
+        let version = match pinned.get(dependency.name.as_str()) {
+            Some(version) => version.to_string(),
+            None => dependency.range.clone(),
+        };
+        if let Some(reason) = unmappable(&version) {
+            pins.unmapped.push(Unmapped {
+                name: dependency.name.clone(),
+                spec: version.clone(),
+                reason,
+            });
+        }
+        roots.push((dependency.clone(), version));
+    }
+
+    Ok(pins.into_import(&roots))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{DependencyKind, Lockfile, Spec};
+
+    const REGISTRY: &str = "https://registry.npmjs.org/";
+
+    fn roots() -> Vec<Dependency> {
+        vec![
+            Dependency::new("a", "^1.0.0", DependencyKind::Prod),
+            Dependency::new("@scope/b", "^2.0.0", DependencyKind::Dev),
+        ]
+    }
+
+    fn versions(import: &Import) -> Vec<String> {
+        import.graph.nodes().map(|(id, _)| id.to_string()).collect()
+    }
+
+    #[test]
+    fn imports_version_5() {
+        let import = import_pnpm_lock(
+            r#"
+lockfileVersion: 5.4
+specifiers:
+  a: ^1.0.0
+  '@scope/b': ^2.0.0
+dependencies:
+  a: 1.1.0_react@18.0.0
+devDependencies:
+  '@scope/b': 2.0.0
+packages:
+  /a/1.1.0_react@18.0.0:
+    resolution: {integrity: sha512-aaaa}
+    dependencies:
+      '@scope/b': 2.0.0
+    dev: false
+  /@scope/b/2.0.0:
+    resolution: {integrity: sha512-bbbb}
+    dev: true
+"#,
+            &roots(),
+            &Npmrc::new(REGISTRY),
+        )
+        .unwrap();
+
+        assert_eq!(versions(&import), vec!["@scope/b@2.0.0", "a@1.1.0"]);
+        assert_eq!(import.graph.roots()[1].range, "^1.0.0");
+        let b = &import
+            .graph
+            .node(&PackageId::new("@scope/b", "2.0.0"))
+            .unwrap()
+            .metadata;
+        assert_eq!(
+            b.dist.tarball,
+            "https://registry.npmjs.org/@scope/b/-/b-2.0.0.tgz"
+        );
+        assert_eq!(b.dist.integrity.as_deref(), Some("sha512-bbbb"));
+    }
+
+    #[test]
+    fn imports_version_6() {
+        let import = import_pnpm_lock(
+            r#"
+lockfileVersion: '6.0'
+dependencies:
+  a:
+    specifier: ^1.0.0
+    version: 1.1.0(react@18.0.0)
+devDependencies:
+  '@scope/b':
+    specifier: ^2.0.0
+    version: 2.0.0
+packages:
+  /a@1.1.0(react@18.0.0):
+    resolution: {integrity: sha512-aaaa}
+    dependencies:
+      '@scope/b': 2.0.0
+  /@scope/b@2.0.0:
+    resolution: {integrity: sha512-bbbb}
+"#,
+            &roots(),
+            &Npmrc::new(REGISTRY),
+        )
+        .unwrap();
+
+        assert_eq!(versions(&import), vec!["@scope/b@2.0.0", "a@1.1.0"]);
+        assert_eq!(import.graph.roots().len(), 2);
+    }
+
+    #[test]
+    fn imports_version_9() {
+        let import = import_pnpm_lock(
+            r#"
+lockfileVersion: '9.0'
+importers:
+  .:
+    dependencies:
+      a:
+        specifier: ^1.0.0
+        version: 1.1.0
+      '@scope/b':
+        specifier: ^2.0.0
+        version: link:../b
+packages:
+  a@1.1.0:
+    resolution: {integrity: sha512-aaaa}
+  c@1.0.0:
+    resolution: {tarball: https://example.com/c.tgz}
+snapshots:
+  a@1.1.0:
+    optionalDependencies:
+      c: 1.0.0
+  c@1.0.0: {}
+"#,
+            &roots(),
+            &Npmrc::new(REGISTRY),
+        )
+        .unwrap();
+
+        assert!(import.graph.is_empty());
+        assert_eq!(import.dropped, vec![PackageId::new("a", "1.1.0")]);
+        assert_eq!(
+            import
+                .unmapped
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>(),
+            vec![
+                "c@1.0.0 (tarball URL)",
+                "@scope/b@link:../b (linked package)"
+            ]
+        );
+    }
+
+    #[test]
+    fn keeps_optional_dependencies_optional() {
+        let import = import_pnpm_lock(
+            r#"
+lockfileVersion: '9.0'
+importers:
+  .:
+    dependencies:
+      a:
+        specifier: ^1.0.0
+        version: 1.1.0
+packages:
+  a@1.1.0:
+    resolution: {integrity: sha512-aaaa}
+  c@1.0.0:
+    resolution: {integrity: sha512-cccc}
+snapshots:
+  a@1.1.0:
+    optionalDependencies:
+      c: 1.0.0
+  c@1.0.0: {}
+"#,
+            &roots()[..1],
+            &Npmrc::new(REGISTRY),
+        )
+        .unwrap();
+
+        let a = import.graph.node(&PackageId::new("a", "1.1.0")).unwrap();
+        assert_eq!(a.edges[0].kind, DependencyKind::Optional);
+        let lockfile = Lockfile::from_graph(&import.graph);
+        assert!(!lockfile.packages["a@1.1.0"].optional);
+        assert!(lockfile.packages["c@1.0.0"].optional);
+    }
+
+    #[test]
+    fn strips_peers_from_transitive_dependencies() {
+        for contents in [
+            r#"
+lockfileVersion: '6.0'
+dependencies:
+  a:
+    specifier: ^1.0.0
+    version: 1.1.0(react@18.0.0)
+packages:
+  /a@1.1.0(react@18.0.0):
+    resolution: {integrity: sha512-aaaa}
+    dependencies:
+      '@scope/b': 2.0.0(react@18.0.0)
+  /@scope/b@2.0.0(react@18.0.0):
+    resolution: {integrity: sha512-bbbb}
+"#,
+            r#"
+lockfileVersion: 5.4
+dependencies:
+  a: 1.1.0_react@18.0.0
+packages:
+  /a/1.1.0_react@18.0.0:
+    resolution: {integrity: sha512-aaaa}
+    dependencies:
+      '@scope/b': 2.0.0_react@18.0.0
+  /@scope/b/2.0.0_react@18.0.0:
+    resolution: {integrity: sha512-bbbb}
+"#,
+        ] {
+            let import = import_pnpm_lock(contents, &roots()[..1], &Npmrc::new(REGISTRY)).unwrap();
+
+            assert_eq!(versions(&import), vec!["@scope/b@2.0.0", "a@1.1.0"]);
+            let a = import.graph.node(&PackageId::new("a", "1.1.0")).unwrap();
+            assert_eq!(a.edges[0].range, "2.0.0");
+            assert_eq!(
+                a.metadata.dependencies.as_ref().unwrap()["@scope/b"],
+                "2.0.0"
+            );
+            // The ranges have to be usable when resolving again
+            assert!(Spec::parse("@scope/b", &a.edges[0].range).is_ok());
+        }
+    }
+}
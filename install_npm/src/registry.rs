@@ -0,0 +1,84 @@
+/// How `name` appears in registry URLs. The slash of a scoped name is
+/// encoded (`@scope%2fname`), as registries expect packuments to be a
+/// single path segment
+pub fn escaped_name(name: &str) -> String {
+    if name.starts_with('@') {
+        name.replacen('/', "%2f", 1)
+    } else {
+        name.to_string()
+    }
+}
+
+/// Appends `path` to `registry`, which may or may not end with a slash and
+/// may have a path of its own (like `https://host/api/npm/`)
+fn join(registry: &str, path: &str) -> String {
+    format!("{}/{path}", registry.trim_end_matches('/'))
+}
+
+/// The URL of the packument of `name` on `registry`
+pub fn packument_url(registry: &str, name: &str) -> String {
+    join(registry, &escaped_name(name))
+}
+
+/// The URL `registry` publishes the tarball of `name@version` at. Unlike
+/// packuments, tarballs keep the slash of scoped names, like npm does
+/// (`@scope/name/-/name-1.0.0.tgz`)
+pub fn tarball_url(registry: &str, name: &str, version: &str) -> String {
+    let basename = name.rsplit('/').next().unwrap_or(name);
+    join(registry, &format!("{name}/-/{basename}-{version}.tgz"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use validate_package_name::validate;
+
+    #[test]
+    fn escapes_scoped_names() {
+        assert_eq!(
+            packument_url("https://registry.npmjs.org", "@types/node"),
+            "https://registry.npmjs.org/@types%2fnode"
+        );
+        assert_eq!(
+            packument_url("https://registry.npmjs.org/", "lodash"),
+            "https://registry.npmjs.org/lodash"
+        );
+    }
+
+    #[test]
+    fn keeps_registry_paths() {
+        for registry in ["https://host/api/npm", "https://host/api/npm/"] {
+            assert_eq!(
+                packument_url(registry, "@scope/a"),
+                "https://host/api/npm/@scope%2fa"
+            );
+            assert_eq!(
+                tarball_url(registry, "@scope/a", "1.0.0"),
+                "https://host/api/npm/@scope/a/-/a-1.0.0.tgz"
+            );
+        }
+        assert_eq!(
+            tarball_url("https://host/", "a", "1.0.0-beta.1"),
+            "https://host/a/-/a-1.0.0-beta.1.tgz"
+        );
+    }
+
+    #[test]
+    fn valid_names_are_one_path_segment() {
+        let registry = "https://host/api/npm/";
+        for name in [
+            "@npm/cool",
+            "@types/node",
+            "some-package",
+            "example.com",
+            "under_score",
+        ] {
+            assert_eq!(validate(name), Ok(()));
+
+            let url = packument_url(registry, name);
+            let segment = url.strip_prefix(registry).unwrap();
+            assert!(!segment.contains('/'), "{url}");
+            assert_eq!(segment.replacen("%2f", "/", 1), name);
+        }
+    }
+}
@@ -0,0 +1,1400 @@
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::future::Future;
+use std::path::Path;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+
+use futures::future::join_all;
+use node_semver::Version;
+use tempdir::TempDir;
+use tokio::sync::{OnceCell, Semaphore};
+
+use crate::extract::{download_url, files};
+use crate::local::{self, Local};
+use crate::overrides::Walk;
+use crate::{
+    fetch_packument, git, hoist, install_package, pack, resolve_spec, select_version,
+    sha512_integrity, Cache, Dependency, DependencyKind, Dist, Edge, Error, GitReference, GitSpec,
+    Graph, Manifest, Metadata, NetworkMode, Npmrc, Overrides, PackageId, PackumentFormat,
+    RawMetadata, RetryPolicy, Spec, Store, VersionSpec,
+};
+
+/// How many registry requests are in flight at once, unless configured otherwise
+pub const DEFAULT_CONCURRENCY: usize = 16;
+
+/// A packument that is fetched once, however many times it's asked for
+type PackumentCell = Arc<OnceCell<Arc<RawMetadata>>>;
+
+/// Packing a git dependency can mean resolving the dependencies of its
+/// `prepare` script, so the future is boxed to break the cycle
+type Packing<'a> = Pin<Box<dyn Future<Output = Result<(String, Vec<u8>), Error>> + 'a>>;
+
+/// Resolves dependencies against a registry. Packuments are fetched
+/// concurrently, and memoized for the lifetime of the resolver, so each
+/// package name is only requested once no matter how often it shows up
+pub struct Resolver {
+    npmrc: Npmrc,
+    cache: Option<Cache>,
+    mode: NetworkMode,
+    retry: RetryPolicy,
+    requests: Semaphore,
+    /// Keyed by name, format and the mode it was fetched with, as a
+    /// packument read from the cache may need fetching again
+    packuments: Mutex<HashMap<(String, PackumentFormat, NetworkMode), PackumentCell>>,
+    /// Previously resolved packages, which win over the registry
+    /// whenever they satisfy a range
+    locked: HashMap<String, HashMap<Version, Metadata>>,
+    /// Packages previously resolved from git, keyed by the `(name, spec)`
+    /// they were asked for with
+    locked_git: HashMap<(String, String), Metadata>,
+    /// Where the dependencies of git dependencies with a `prepare` script
+    /// are installed from
+    store: Option<Store>,
+    /// The project's overrides of what transitive dependencies ask for
+    overrides: Overrides,
+}
+
+impl Resolver {
+    pub fn new(registry: &str) -> Self {
+        Self {
+            npmrc: Npmrc::new(registry),
+            cache: None,
+            mode: NetworkMode::default(),
+            retry: RetryPolicy::default(),
+            requests: Semaphore::new(DEFAULT_CONCURRENCY),
+            packuments: Mutex::new(HashMap::new()),
+            locked: HashMap::new(),
+            locked_git: HashMap::new(),
+            store: None,
+            overrides: Overrides::default(),
+        }
+    }
+
+    /// Prefers `packages` (usually from a lockfile) over the registry, so
+    /// re-resolving only changes what no longer satisfies its range
+    pub fn with_locked(mut self, packages: Vec<Metadata>) -> Self {
+        for metadata in packages {
+            // Git and local dependencies are locked to where they come
+            // from, not to a version of the registry. Git dependencies are
+            // locked with [Resolver::with_locked_git] instead
+            let resolved = &metadata.dist.tarball;
+            if git::parse_resolved(&metadata.name, resolved).is_some()
+                || local::parse_resolved(&metadata.name, resolved).is_some()
+            {
+                continue;
+            }
+            if let Ok(version) = Version::parse(&metadata.version) {
+                self.locked
+                    .entry(metadata.name.clone())
+                    .or_default()
+                    .insert(version, metadata);
+            }
+        }
+        self
+    }
+
+    /// Keeps git dependencies on the commit in `packages` (usually from a
+    /// lockfile) that they were locked to, as long as they're asked for
+    /// with the same spec, instead of asking the remote where a branch or
+    /// `semver:` range points now
+    pub fn with_locked_git(mut self, packages: HashMap<(String, String), Metadata>) -> Self {
+        self.locked_git = packages;
+        self
+    }
+
+    /// Fetches packages from the registries configured in `npmrc`, with
+    /// their credentials, instead of the one passed to [Resolver::new]
+    pub fn with_npmrc(mut self, npmrc: Npmrc) -> Self {
+        self.npmrc = npmrc;
+        self
+    }
+
+    /// Installs the dependencies of git dependencies that need building
+    /// from `store`
+    pub fn with_store(mut self, store: Store) -> Self {
+        self.store = Some(store);
+        self
+    }
+
+    /// Resolves transitive dependencies matching one of `overrides` with
+    /// its spec instead of their own range
+    pub fn with_overrides(mut self, overrides: Overrides) -> Self {
+        self.overrides = overrides;
+        self
+    }
+
+    /// Caches packuments (and packed git dependencies) on disk,
+    /// revalidating them instead of downloading them again
+    pub fn with_cache(mut self, cache: Cache) -> Self {
+        self.cache = Some(cache);
+        self
+    }
+
+    /// Whether packuments may be fetched from the registry, rather than
+    /// only read from the cache
+    pub fn with_network_mode(mut self, mode: NetworkMode) -> Self {
+        self.mode = mode;
+        self
+    }
+
+    /// How failed registry requests are retried
+    pub fn with_retry(mut self, retry: RetryPolicy) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    /// Limits how many registry requests can be in flight at once. Requests
+    /// waiting to be retried don't count
+    pub fn with_concurrency(mut self, concurrency: usize) -> Self {
+        self.requests = Semaphore::new(concurrency.max(1));
+        self
+    }
+
+    /// Fetches the packument of `name`, in `format`. Concurrent calls for
+    /// the same name share a single request
+    async fn packument(
+        &self,
+        name: &str,
+        format: PackumentFormat,
+        mode: NetworkMode,
+    ) -> Result<Arc<RawMetadata>, Error> {
+        let cell = self
+            .packuments
+            .lock()
+            .unwrap()
+            .entry((name.to_string(), format, mode))
+            .or_default()
+            .clone();
+
+        cell.get_or_try_init(|| async {
+            fetch_packument(
+                name,
+                &self.npmrc,
+                format,
+                self.cache.as_ref(),
+                mode,
+                &self.retry,
+                Some(&self.requests),
+            )
+            .await
+            .map(Arc::new)
+        })
+        .await
+        .cloned()
+    }
+
+    /// Resolves `name@spec` against the registry
+    async fn resolve_registry(&self, name: &str, spec: &VersionSpec) -> Result<Metadata, Error> {
+        // Tags can move, so only ranges are matched against locked packages
+        if let (Some(locked), VersionSpec::Range(range)) = (self.locked.get(name), spec) {
+            if let Some(version) = select_version(locked, &HashMap::new(), range) {
+                return Ok(locked[version].clone());
+            }
+        }
+
+        let packument = self.packument(name, PackumentFormat::Abbreviated, self.mode);
+        match resolve_spec(name, &*packument.await?, spec) {
+            // The cached packument may predate the version we're after
+            Err(Error::RangeNotSatisfied | Error::DistTagNotFound { .. })
+                if self.mode == NetworkMode::PreferOffline =>
+            {
+                let packument =
+                    self.packument(name, PackumentFormat::Abbreviated, NetworkMode::Online);
+                resolve_spec(name, &*packument.await?, spec)
+            }
+            resolved => resolved,
+        }
+    }
+
+    /// Clones `url` at `reference` and packs it, running its `prepare`
+    /// script first if it has one. Packed repositories are cached by commit,
+    /// so a commit is only ever cloned once
+    fn pack_git<'a>(&'a self, name: &'a str, url: &'a str, reference: &'a str) -> Packing<'a> {
+        Box::pin(async move {
+            let error = |cause: &str| Error::Git {
+                name: name.to_string(),
+                url: url.to_string(),
+                cause: cause.to_string(),
+            };
+            let cache = self
+                .cache
+                .as_ref()
+                .ok_or_else(|| error("there is no cache to keep it in"))?;
+            if git::is_commit(reference) {
+                if let Some(tarball) = cache.read_git(reference) {
+                    return Ok((reference.to_string(), tarball));
+                }
+            }
+            if self.mode == NetworkMode::Offline {
+                return Err(Error::NotCached {
+                    name: name.to_string(),
+                });
+            }
+
+            let dir = TempDir::new("vortex-git").map_err(|_| Error::IoError)?;
+            let repository = dir.path().join("repository");
+            let sha = git::checkout(name, url, reference, &repository).await?;
+            if let Some(tarball) = cache.read_git(&sha) {
+                return Ok((sha, tarball));
+            }
+
+            let manifest: Manifest = fs::read_to_string(repository.join("package.json"))
+                .ok()
+                .and_then(|contents| serde_json::from_str(&contents).ok())
+                .ok_or_else(|| error("the repository has no valid package.json"))?;
+            let prepare = manifest
+                .scripts
+                .as_ref()
+                .and_then(|scripts| scripts.get("prepare"));
+            if let Some(script) = prepare {
+                // Building usually needs devDependencies, like npm installs
+                self.install_into(&repository, &manifest.all_dependencies())
+                    .await?;
+                git::prepare(name, url, &repository, script).await?;
+            }
+
+            let tarball = pack(&repository)?;
+            cache.write_git(&sha, &tarball)?;
+            Ok((sha, tarball))
+        })
+    }
+
+    /// Resolves and installs `dependencies` into `dir`
+    async fn install_into(&self, dir: &Path, dependencies: &[Dependency]) -> Result<(), Error> {
+        let graph = self.resolve(dependencies).await?;
+        for (_, node) in graph.nodes() {
+            self.fetch_git(&node.metadata).await?;
+        }
+        for (path, id) in hoist(&graph) {
+            let metadata = &graph.node(&id).unwrap().metadata;
+            let destination = dir.join(path);
+            match &self.store {
+                Some(store) => store.install(metadata, &destination).await?,
+                None => install_package(metadata, &self.npmrc, &destination).await?,
+            }
+        }
+        Ok(())
+    }
+
+    /// Resolves a git dependency to the commit its reference points at,
+    /// packing the repository as it was at that commit
+    async fn resolve_git(&self, name: &str, spec: &GitSpec) -> Result<Metadata, Error> {
+        // Only a full commit can be found in the cache without asking the
+        // remote where the reference points
+        if self.mode == NetworkMode::Offline {
+            match &spec.reference {
+                GitReference::Committish(committish) if git::is_commit(committish) => {}
+                _ => {
+                    return Err(Error::NotCached {
+                        name: name.to_string(),
+                    })
+                }
+            }
+        }
+        let reference = match git::resolve_commit(name, spec).await? {
+            Some(sha) => sha,
+            // An abbreviated commit, which only a clone can expand
+            None => match &spec.reference {
+                GitReference::Committish(committish) => committish.clone(),
+                _ => unreachable!("only commits need a clone to resolve"),
+            },
+        };
+        let (sha, tarball) = self.pack_git(name, &spec.url, &reference).await?;
+
+        let manifest: Manifest = files(&tarball)?
+            .into_iter()
+            .find(|file| file.path == Path::new("package.json"))
+            .and_then(|file| serde_json::from_slice(&file.contents).ok())
+            .unwrap_or_default();
+
+        Ok(manifest.into_metadata(
+            name,
+            Dist {
+                tarball: git::resolved_url(&spec.url, &sha),
+                shasum: String::new(),
+                // Like npm, git dependencies are identified by their commit,
+                // as packing them again doesn't always produce the same bytes
+                integrity: None,
+            },
+        ))
+    }
+
+    /// Resolves a dependency on a tarball URL by downloading it, so its
+    /// integrity can be locked. A URL that is already locked isn't
+    /// downloaded again
+    async fn resolve_tarball(&self, name: &str, url: &str) -> Result<Metadata, Error> {
+        let locked = self
+            .locked
+            .values()
+            .flat_map(HashMap::values)
+            .find(|metadata| metadata.dist.tarball == url && metadata.dist.integrity.is_some());
+        if let Some(metadata) = locked {
+            return Ok(metadata.clone());
+        }
+        if self.mode == NetworkMode::Offline {
+            return Err(Error::NotCached {
+                name: name.to_string(),
+            });
+        }
+
+        let tarball =
+            download_url(name, url, &self.npmrc, &self.retry, Some(&self.requests)).await?;
+
+        let manifest: Manifest = files(&tarball)?
+            .into_iter()
+            .find(|file| file.path == Path::new("package.json"))
+            .and_then(|file| serde_json::from_slice(&file.contents).ok())
+            .ok_or(Error::InvalidTarball)?;
+        let integrity = sha512_integrity(&tarball);
+
+        // Saves the store from downloading it again
+        if let Some(store) = &self.store {
+            store.insert(&integrity, &tarball)?;
+        }
+        Ok(manifest.into_metadata(
+            name,
+            Dist {
+                tarball: url.to_string(),
+                shasum: String::new(),
+                integrity: Some(integrity),
+            },
+        ))
+    }
+
+    /// Makes sure a package locked to a git commit is packed in the cache,
+    /// so a [Store] can install it. Other packages are left alone
+    pub async fn fetch_git(&self, metadata: &Metadata) -> Result<(), Error> {
+        if let Some((url, sha)) = git::parse_resolved(&metadata.name, &metadata.dist.tarball) {
+            self.pack_git(&metadata.name, &url, &sha).await?;
+        }
+        Ok(())
+    }
+
+    /// When each version of `name` was published, by version, along with
+    /// `created` and `modified`. Only the full packument has these, so it's
+    /// fetched (and cached) separately from the abbreviated one resolving
+    /// uses
+    pub async fn publish_times(&self, name: &str) -> Result<HashMap<String, String>, Error> {
+        let packument = self
+            .packument(name, PackumentFormat::Full, self.mode)
+            .await?;
+        Ok(packument
+            .time
+            .iter()
+            .filter_map(|(key, time)| Some((key.clone(), time.as_str()?.to_string())))
+            .collect())
+    }
+
+    /// Resolves a dependency with whatever its spec points at
+    async fn resolve_one(&self, dependency: &Dependency) -> Result<Metadata, Error> {
+        match Spec::parse(&dependency.name, &dependency.range)? {
+            Spec::Registry(version) => self.resolve_registry(&dependency.name, &version).await,
+            Spec::Alias { name, version } => self.resolve_registry(&name, &version).await,
+            Spec::Git(spec) => {
+                let key = (dependency.name.clone(), dependency.range.clone());
+                match self.locked_git.get(&key) {
+                    Some(metadata) => Ok(metadata.clone()),
+                    None => self.resolve_git(&dependency.name, &spec).await,
+                }
+            }
+            Spec::RemoteTarball(url) => self.resolve_tarball(&dependency.name, &url).await,
+            spec @ (Spec::Directory(_) | Spec::LocalTarball(_) | Spec::Link(_)) => {
+                Local::from_spec(spec).unwrap().metadata(&dependency.name)
+            }
+            spec => Err(Error::UnsupportedSpec {
+                name: dependency.name.clone(),
+                kind: spec.to_string(),
+            }),
+        }
+    }
+
+    /// Resolves `dependencies` and everything they depend on into a [Graph]
+    /// whose roots are `dependencies`.
+    ///
+    /// The graph is walked one level at a time, resolving every new
+    /// `name@range` of a level concurrently. Each `name@version` is only
+    /// walked once, which is also what keeps cycles from being walked forever.
+    ///
+    /// Like npm 7+, peer dependencies resolve to what the dependent of the
+    /// package provides, or failing that the project. Peers nobody provides
+    /// are installed for the project, unless they're optional. A package is
+    /// only resolved once, with the peers of the first dependent reaching
+    /// it. Whether those satisfy the peers, and match what its other
+    /// dependents provide, is up to [crate::peer_conflicts]
+    pub async fn resolve(&self, dependencies: &[Dependency]) -> Result<Graph, Error> {
+        let mut graph = Graph::new();
+        let mut requested: HashMap<String, PackageId> = HashMap::new();
+        // Optional dependencies that couldn't be resolved, and are left out
+        let mut skipped: HashSet<String> = HashSet::new();
+        let roots: HashMap<&str, &str> = dependencies
+            .iter()
+            .map(|dependency| (dependency.name.as_str(), dependency.range.as_str()))
+            .collect();
+        // Peers nobody provided, which the project provides from then on,
+        // with the range of the first package wanting them
+        let mut installed_peers: HashMap<String, String> = HashMap::new();
+        // The path each package was first reached through, from a
+        // dependency of the project down to the package, for overrides.
+        // Other paths are checked once everything is resolved
+        let mut ancestry: HashMap<PackageId, Vec<PackageId>> = HashMap::new();
+        // Each dependency along with the range it's resolved with, which is
+        // different for peers and overridden dependencies (and whether it
+        // was overridden)
+        let mut level: Vec<(Option<PackageId>, Dependency, String, bool)> = dependencies
+            .iter()
+            .map(|dependency| (None, dependency.clone(), dependency.range.clone(), false))
+            .collect();
+
+        while !level.is_empty() {
+            let mut pending: Vec<(String, Dependency, Option<PackageId>)> = Vec::new();
+            for (from, dependency, range, _) in level.iter() {
+                let key = format!("{}@{}", dependency.name, range);
+                // Something needs what an optional dependency couldn't get,
+                // so it's resolved again, failing the resolution this time
+                if dependency.kind != DependencyKind::Optional {
+                    skipped.remove(&key);
+                }
+                if !requested.contains_key(&key)
+                    && !skipped.contains(&key)
+                    && !pending.iter().any(|(k, ..)| k == &key)
+                {
+                    let request = Dependency::new(&dependency.name, range, dependency.kind);
+                    pending.push((key, request, from.clone()));
+                }
+            }
+
+            let resolved = join_all(
+                pending
+                    .iter()
+                    .map(|(_, dependency, _)| self.resolve_one(dependency)),
+            )
+            .await;
+
+            let mut next: Vec<(Option<PackageId>, Dependency, String, bool)> = Vec::new();
+            for ((key, _, from), metadata) in pending.iter().zip(resolved) {
+                let metadata = match metadata {
+                    Ok(metadata) => metadata,
+                    // Like npm, optional dependencies are allowed to fail
+                    Err(_)
+                        if level
+                            .iter()
+                            .filter(|(_, dependency, range, _)| {
+                                &format!("{}@{}", dependency.name, range) == key
+                            })
+                            .all(|(_, dependency, ..)| {
+                                dependency.kind == DependencyKind::Optional
+                            }) =>
+                    {
+                        skipped.insert(key.clone());
+                        continue;
+                    }
+                    Err(e) => return Err(e),
+                };
+                let id = PackageId::from(&metadata);
+                requested.insert(key.clone(), id.clone());
+                if graph.contains(&id) {
+                    continue;
+                }
+                let mut path = from
+                    .as_ref()
+                    .and_then(|from| ancestry.get(from).cloned())
+                    .unwrap_or_default();
+                path.push(id.clone());
+
+                let optional = metadata.optional_dependencies.clone().unwrap_or_default();
+                let mut dependencies = metadata.dependencies.clone().unwrap_or_default();
+                dependencies.extend(optional.clone());
+                let mut children: Vec<(Dependency, String, bool)> = dependencies
+                    .iter()
+                    .map(|(name, range)| {
+                        let kind = if optional.contains_key(name) {
+                            DependencyKind::Optional
+                        } else {
+                            DependencyKind::Prod
+                        };
+                        let dependency = Dependency::new(name, range, kind);
+                        match self.overrides.find(&path, &dependency) {
+                            Some(spec) => (dependency, spec.to_string(), true),
+                            None => (dependency, range.clone(), false),
+                        }
+                    })
+                    .collect();
+
+                // What the package that brought this one in provides
+                let provided = from
+                    .as_ref()
+                    .and_then(|from| graph.node(from))
+                    .and_then(|node| node.metadata.dependencies.clone())
+                    .unwrap_or_default();
+                for (name, range) in metadata.peer_dependencies.iter().flatten() {
+                    if dependencies.contains_key(name) {
+                        continue;
+                    }
+                    let provided = provided
+                        .get(name)
+                        .map(String::as_str)
+                        .or_else(|| roots.get(name.as_str()).copied())
+                        .or_else(|| installed_peers.get(name).map(String::as_str));
+                    let resolve_with = match provided {
+                        Some(provided) => provided.to_string(),
+                        None if metadata.is_optional_peer(name) => continue,
+                        None => {
+                            installed_peers.insert(name.clone(), range.clone());
+                            range.clone()
+                        }
+                    };
+                    children.push((
+                        Dependency::new(name, range, DependencyKind::Peer),
+                        resolve_with,
+                        false,
+                    ));
+                }
+                children.sort_by(|(a, ..), (b, ..)| a.name.cmp(&b.name));
+
+                graph.add_node(metadata);
+                ancestry.insert(id.clone(), path);
+                next.extend(children.into_iter().map(|(dependency, range, overridden)| {
+                    (Some(id.clone()), dependency, range, overridden)
+                }));
+            }
+
+            for (from, dependency, range, overridden) in level {
+                let to = match requested.get(&format!("{}@{}", dependency.name, range)) {
+                    Some(to) => to.clone(),
+                    None => continue,
+                };
+                graph.add_edge(
+                    from.as_ref(),
+                    Edge {
+                        name: dependency.name,
+                        range: dependency.range,
+                        kind: dependency.kind,
+                        to,
+                        overridden: overridden.then_some(range),
+                    },
+                );
+            }
+
+            level = next;
+        }
+
+        self.check_overrides(&graph, &ancestry)?;
+        Ok(graph)
+    }
+
+    /// Fails if a package reached through several paths would have one of
+    /// its dependencies overridden differently along them. It's resolved
+    /// once, with the overrides of the path it was first reached through
+    fn check_overrides(
+        &self,
+        graph: &Graph,
+        ancestry: &HashMap<PackageId, Vec<PackageId>>,
+    ) -> Result<(), Error> {
+        let describe = |path: &[&PackageId]| {
+            path.iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>()
+                .join(" > ")
+        };
+
+        // Paths that got as far along every rule lead to the same
+        // overrides from there on, so only one of them is walked
+        let mut walked: HashSet<(&PackageId, Walk)> = HashSet::new();
+        let start = self.overrides.start();
+        let mut stack: Vec<(Vec<&PackageId>, Walk)> = graph
+            .roots()
+            .iter()
+            .map(|edge| (vec![&edge.to], self.overrides.step(&start, &edge.to)))
+            .collect();
+        while let Some((path, walk)) = stack.pop() {
+            let id = path[path.len() - 1];
+            let node = match graph.node(id) {
+                Some(node) => node,
+                None => continue,
+            };
+            if !walked.insert((id, walk.clone())) {
+                continue;
+            }
+            for edge in &node.edges {
+                let dependency = Dependency::new(&edge.name, &edge.range, edge.kind);
+                if edge.kind != DependencyKind::Peer
+                    && self.overrides.find_at(&walk, &dependency) != edge.overridden.as_deref()
+                {
+                    let first: Vec<&PackageId> = ancestry[id].iter().collect();
+                    return Err(Error::AmbiguousOverride {
+                        name: edge.name.clone(),
+                        first: describe(&first),
+                        second: describe(&path),
+                    });
+                }
+                let mut next = path.clone();
+                next.push(&edge.to);
+                stack.push((next, self.overrides.step(&walk, &edge.to)));
+            }
+        }
+        Ok(())
+    }
+
+    /// Makes `name` resolve from `packument` without asking the registry
+    #[cfg(test)]
+    pub(crate) fn seed(&self, name: &str, packument: RawMetadata) {
+        self.packuments.lock().unwrap().insert(
+            (name.to_string(), PackumentFormat::Abbreviated, self.mode),
+            Arc::new(OnceCell::new_with(Some(Arc::new(packument)))),
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::extract::files;
+    use crate::extract::tests::tarball;
+    use crate::git::tests::{repository, run};
+    use crate::tests::{binary_response, metadata, packument, response, serve};
+    use crate::{Lockfile, PeerDependencyMeta};
+    use std::path::PathBuf;
+    use std::time::Duration;
+    use tempdir::TempDir;
+
+    macro_rules! aw {
+        ($e:expr) => {
+            tokio_test::block_on($e)
+        };
+    }
+
+    #[test]
+    fn walks_cycles_once() {
+        let resolver = Resolver::new("https://registry.npmjs.org");
+        resolver.seed(
+            "a",
+            packument(vec![metadata("a", "1.0.0", &[("b", "^1.0.0")])]),
+        );
+        resolver.seed(
+            "b",
+            packument(vec![metadata("b", "1.0.0", &[("a", "*"), ("b", "1")])]),
+        );
+
+        let root = Dependency::new("a", "1.0.0", DependencyKind::Prod);
+        let graph = aw!(resolver.resolve(&[root])).unwrap();
+
+        let a = PackageId::new("a", "1.0.0");
+        let b = PackageId::new("b", "1.0.0");
+        assert_eq!(graph.len(), 2);
+        assert_eq!(graph.roots()[0].to, a);
+        assert_eq!(graph.node(&a).unwrap().edges[0].to, b);
+        assert_eq!(
+            graph
+                .node(&b)
+                .unwrap()
+                .edges
+                .iter()
+                .map(|edge| (edge.range.as_str(), &edge.to))
+                .collect::<Vec<_>>(),
+            vec![("*", &a), ("1", &b)]
+        );
+    }
+
+    #[test]
+    fn prefers_locked_packages() {
+        let resolver = Resolver::new("https://registry.npmjs.org").with_locked(vec![
+            metadata("a", "1.0.0", &[]),
+            metadata("b", "1.0.0", &[]),
+        ]);
+        resolver.seed(
+            "b",
+            packument(vec![
+                metadata("b", "1.0.0", &[]),
+                metadata("b", "2.0.0", &[]),
+            ]),
+        );
+
+        // `a` isn't seeded, so resolving it from the registry would fail
+        let graph = aw!(resolver.resolve(&[
+            Dependency::new("a", "^1.0.0", DependencyKind::Prod),
+            Dependency::new("b", "^2.0.0", DependencyKind::Prod),
+        ]))
+        .unwrap();
+
+        assert_eq!(
+            graph
+                .nodes()
+                .map(|(id, _)| id.to_string())
+                .collect::<Vec<_>>(),
+            vec!["a@1.0.0", "b@2.0.0"]
+        );
+    }
+
+    #[test]
+    fn prefer_offline_refetches_stale_packuments() {
+        let (url, requests) = serve(vec![response(
+            "200 OK",
+            &[],
+            r#"{"versions":{"2.0.0":{"name":"a","version":"2.0.0","dist":{"tarball":"","shasum":""}}}}"#,
+        )]);
+        let resolver = Resolver::new(&url).with_network_mode(NetworkMode::PreferOffline);
+        resolver.seed("a", packument(vec![metadata("a", "1.0.0", &[])]));
+
+        let graph =
+            aw!(resolver.resolve(&[Dependency::new("a", "^2.0.0", DependencyKind::Prod)])).unwrap();
+
+        assert_eq!(graph.roots()[0].to, PackageId::new("a", "2.0.0"));
+        assert_eq!(requests.join().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn resolves_aliases_to_the_real_package() {
+        let resolver = Resolver::new("https://registry.npmjs.org");
+        resolver.seed("b", packument(vec![metadata("b", "2.0.0", &[])]));
+
+        let graph =
+            aw!(resolver.resolve(&[Dependency::new("a", "npm:b@^2.0.0", DependencyKind::Prod)]))
+                .unwrap();
+
+        assert_eq!(graph.roots()[0].name, "a");
+        assert_eq!(graph.roots()[0].to, PackageId::new("b", "2.0.0"));
+    }
+
+    #[test]
+    fn shares_packages_between_roots() {
+        let resolver = Resolver::new("https://registry.npmjs.org").with_concurrency(2);
+        resolver.seed(
+            "a",
+            packument(vec![metadata("a", "1.0.0", &[("c", "^1.0.0")])]),
+        );
+        resolver.seed(
+            "b",
+            packument(vec![metadata("b", "1.0.0", &[("c", "~1.2.0")])]),
+        );
+        resolver.seed(
+            "c",
+            packument(vec![
+                metadata("c", "1.2.0", &[]),
+                metadata("c", "1.3.0", &[]),
+            ]),
+        );
+
+        let graph = aw!(resolver.resolve(&[
+            Dependency::new("a", "*", DependencyKind::Prod),
+            Dependency::new("b", "*", DependencyKind::Dev),
+        ]))
+        .unwrap();
+
+        assert_eq!(
+            graph
+                .nodes()
+                .map(|(id, _)| id.to_string())
+                .collect::<Vec<_>>(),
+            vec!["a@1.0.0", "b@1.0.0", "c@1.2.0", "c@1.3.0"]
+        );
+        assert_eq!(graph.roots()[1].kind, DependencyKind::Dev);
+    }
+
+    #[test]
+    fn shares_one_request_between_concurrent_dependents() {
+        let (url, requests) = serve(vec![response(
+            "200 OK",
+            &[],
+            r#"{"versions":{
+                "1.2.0":{"name":"c","version":"1.2.0","dist":{"tarball":"","shasum":""}},
+                "1.3.0":{"name":"c","version":"1.3.0","dist":{"tarball":"","shasum":""}}
+            }}"#,
+        )]);
+        let resolver = Resolver::new(&url).with_retry(RetryPolicy::none());
+        resolver.seed(
+            "a",
+            packument(vec![metadata("a", "1.0.0", &[("c", "^1.0.0")])]),
+        );
+        resolver.seed(
+            "b",
+            packument(vec![metadata("b", "1.0.0", &[("c", "~1.2.0")])]),
+        );
+
+        // Both ranges of c are resolved at once, and wait on one request
+        let graph = aw!(resolver.resolve(&[
+            Dependency::new("a", "^1.0.0", DependencyKind::Prod),
+            Dependency::new("b", "^1.0.0", DependencyKind::Prod),
+        ]))
+        .unwrap();
+
+        assert!(graph.contains(&PackageId::new("c", "1.2.0")));
+        assert!(graph.contains(&PackageId::new("c", "1.3.0")));
+        assert_eq!(requests.join().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn fetches_the_full_packument_for_publish_times() {
+        let (url, requests) = serve(vec![
+            response(
+                "200 OK",
+                &[],
+                r#"{"versions":{
+                    "1.0.0":{"name":"a","version":"1.0.0","dist":{"tarball":"","shasum":""}}
+                }}"#,
+            ),
+            response(
+                "200 OK",
+                &[],
+                r#"{"versions":{
+                    "1.0.0":{"name":"a","version":"1.0.0","dist":{"tarball":"","shasum":""}}
+                },"time":{
+                    "created":"2020-01-01T00:00:00.000Z","1.0.0":"2020-01-02T00:00:00.000Z"
+                }}"#,
+            ),
+        ]);
+        let dir = TempDir::new("vortex-cache").unwrap();
+        let cache = Cache::new(dir.path());
+        let resolver = Resolver::new(&url)
+            .with_cache(cache.clone())
+            .with_retry(RetryPolicy::none());
+
+        aw!(resolver.resolve(&[Dependency::new("a", "^1.0.0", DependencyKind::Prod)])).unwrap();
+        let times = aw!(resolver.publish_times("a")).unwrap();
+
+        assert_eq!(times["1.0.0"], "2020-01-02T00:00:00.000Z");
+        assert_eq!(times["created"], "2020-01-01T00:00:00.000Z");
+        let requests = requests.join().unwrap();
+        assert!(requests[0].contains("accept: application/vnd.npm.install-v1+json"));
+        assert!(requests[1].contains("accept: application/json\r\n"));
+        // Each document is cached on its own
+        let read = |format| cache.read(&url, "a", format).unwrap().body;
+        assert!(!read(PackumentFormat::Abbreviated).contains("time"));
+        assert!(read(PackumentFormat::Full).contains("time"));
+    }
+
+    #[test]
+    fn releases_requests_while_backing_off() {
+        let (url, requests) = serve(vec![
+            response("503 Service Unavailable", &[], ""),
+            response(
+                "200 OK",
+                &[],
+                r#"{"versions":{
+                    "1.0.0":{"name":"b","version":"1.0.0","dist":{"tarball":"","shasum":""}}
+                }}"#,
+            ),
+            response(
+                "200 OK",
+                &[],
+                r#"{"versions":{
+                    "1.0.0":{"name":"a","version":"1.0.0","dist":{"tarball":"","shasum":""}}
+                }}"#,
+            ),
+        ]);
+        let resolver = Resolver::new(&url)
+            .with_concurrency(1)
+            .with_retry(RetryPolicy {
+                retries: 1,
+                min_delay: Duration::from_millis(300),
+                ..RetryPolicy::default()
+            });
+
+        // b is fetched while a waits to be retried, which leaves the only
+        // request free halfway through the wait
+        let roots = [
+            Dependency::new("a", "^1.0.0", DependencyKind::Prod),
+            Dependency::new("b", "^1.0.0", DependencyKind::Prod),
+        ];
+        let (graph, available) = aw!(async {
+            futures::join!(resolver.resolve(&roots), async {
+                tokio::time::sleep(Duration::from_millis(150)).await;
+                resolver.requests.available_permits()
+            })
+        });
+        let graph = graph.unwrap();
+
+        assert_eq!(available, 1);
+
+        assert!(graph.contains(&PackageId::new("a", "1.0.0")));
+        assert!(graph.contains(&PackageId::new("b", "1.0.0")));
+        let requests = requests.join().unwrap();
+        assert!(requests[0].starts_with("get /a "));
+        assert!(requests[1].starts_with("get /b "));
+        assert!(requests[2].starts_with("get /a "));
+    }
+
+    #[test]
+    fn resolves_git_dependencies_to_commits() {
+        let dir = TempDir::new("vortex-git").unwrap();
+        let url = repository(
+            dir.path(),
+            &[
+                (
+                    "v1.0.0",
+                    &[("package.json", r#"{"name":"b","version":"1.0.0"}"#)],
+                ),
+                (
+                    "v1.1.0",
+                    &[(
+                        "package.json",
+                        r#"{"name":"b","version":"1.1.0","scripts":{"prepare":"echo built > built.txt"}}"#,
+                    )],
+                ),
+                (
+                    "v2.0.0",
+                    &[("package.json", r#"{"name":"b","version":"2.0.0"}"#)],
+                ),
+            ],
+        );
+        let sha = run(Path::new(&url), &["rev-parse", "v1.1.0"]);
+        let sha = sha.trim();
+        let cache = Cache::new(dir.path().join("cache"));
+        let resolver = Resolver::new("https://registry.npmjs.org").with_cache(cache.clone());
+
+        let graph = aw!(resolver.resolve(&[Dependency::new(
+            "a",
+            &format!("git+file://{url}#semver:^1.0.0"),
+            DependencyKind::Prod
+        )]))
+        .unwrap();
+
+        let node = graph.node(&graph.roots()[0].to).unwrap();
+        assert_eq!(node.metadata.name, "b");
+        assert_eq!(node.metadata.version, "1.1.0");
+        assert_eq!(
+            node.metadata.dist.tarball,
+            format!("git+file://{url}#{sha}")
+        );
+        // Packed after its prepare script ran, and cached by commit
+        let tarball = cache.read_git(sha).unwrap();
+        assert_eq!(
+            files(&tarball)
+                .unwrap()
+                .into_iter()
+                .map(|file| file.path)
+                .collect::<Vec<_>>(),
+            vec![PathBuf::from("built.txt"), PathBuf::from("package.json")]
+        );
+
+        // Once locked, the remote isn't asked where the range points again
+        let locked = Lockfile::from_graph(&graph).locked_git();
+        fs::rename(&url, dir.path().join("moved.git")).unwrap();
+        let resolver = Resolver::new("https://registry.npmjs.org")
+            .with_cache(cache)
+            .with_locked_git(locked);
+        let resolve = |range: &str| {
+            aw!(resolver.resolve(&[Dependency::new("a", range, DependencyKind::Prod)]))
+        };
+        assert_eq!(
+            resolve(&format!("git+file://{url}#semver:^1.0.0"))
+                .unwrap()
+                .roots()[0]
+                .to,
+            PackageId::new("b", "1.1.0")
+        );
+        assert!(resolve(&format!("git+file://{url}#semver:^2.0.0")).is_err());
+    }
+
+    #[test]
+    fn resolves_only_cached_git_commits_offline() {
+        let dir = TempDir::new("vortex-git").unwrap();
+        let url = repository(
+            dir.path(),
+            &[
+                (
+                    "v1.0.0",
+                    &[("package.json", r#"{"name":"b","version":"1.0.0"}"#)],
+                ),
+                (
+                    "v2.0.0",
+                    &[("package.json", r#"{"name":"b","version":"2.0.0"}"#)],
+                ),
+            ],
+        );
+        let first = run(Path::new(&url), &["rev-parse", "v1.0.0"]);
+        let second = run(Path::new(&url), &["rev-parse", "v2.0.0"]);
+        let cache = Cache::new(dir.path().join("cache"));
+        let resolver = Resolver::new("https://registry.npmjs.org").with_cache(cache.clone());
+        aw!(resolver.resolve(&[Dependency::new(
+            "b",
+            &format!("git+file://{url}#v1.0.0"),
+            DependencyKind::Prod
+        )]))
+        .unwrap();
+        fs::remove_dir_all(&url).unwrap();
+
+        let resolver = Resolver::new("https://registry.npmjs.org")
+            .with_cache(cache)
+            .with_network_mode(NetworkMode::Offline);
+        let resolve = |reference: &str| {
+            let range = format!("git+file://{url}#{reference}");
+            aw!(resolver.resolve(&[Dependency::new("b", &range, DependencyKind::Prod)]))
+        };
+
+        assert_eq!(
+            resolve(first.trim()).unwrap().roots()[0].to,
+            PackageId::new("b", "1.0.0")
+        );
+        let not_cached = Err(Error::NotCached {
+            name: "b".to_string(),
+        });
+        for reference in [second.trim(), "v1.0.0", "semver:^1.0.0", "main", ""] {
+            assert_eq!(resolve(reference).map(|_| ()), not_cached);
+        }
+    }
+
+    #[test]
+    fn resolves_tarball_urls() {
+        let contents = tarball(&[(
+            "package/package.json",
+            r#"{"name":"foo","version":"1.0.0","dependencies":{"b":"^2.0.0"}}"#,
+        )]);
+        let (url, requests) = serve(vec![binary_response(&contents)]);
+        let resolver = Resolver::new("https://registry.npmjs.org");
+        resolver.seed("b", packument(vec![metadata("b", "2.0.0", &[])]));
+        let url = format!("{url}/foo-1.0.0.tgz");
+
+        let graph =
+            aw!(resolver.resolve(&[Dependency::new("bar", &url, DependencyKind::Prod)])).unwrap();
+
+        assert_eq!(graph.roots()[0].name, "bar");
+        let node = graph.node(&PackageId::new("foo", "1.0.0")).unwrap();
+        assert_eq!(node.metadata.dist.tarball, url);
+        assert_eq!(
+            node.metadata.dist.integrity,
+            Some(sha512_integrity(&contents))
+        );
+        assert_eq!(node.edges[0].to, PackageId::new("b", "2.0.0"));
+        assert_eq!(requests.join().unwrap().len(), 1);
+
+        // Once locked, the URL isn't downloaded again
+        let resolver = Resolver::new("https://registry.npmjs.org")
+            .with_locked(vec![node.metadata.clone()])
+            .with_network_mode(NetworkMode::Offline);
+        resolver.seed("b", packument(vec![metadata("b", "2.0.0", &[])]));
+        let graph =
+            aw!(resolver.resolve(&[Dependency::new("bar", &url, DependencyKind::Prod)])).unwrap();
+        assert_eq!(graph.roots()[0].to, PackageId::new("foo", "1.0.0"));
+    }
+
+    /// `metadata`, with `peers` as its peer dependencies
+    fn with_peers(mut metadata: Metadata, peers: &[(&str, &str)]) -> Metadata {
+        metadata.peer_dependencies = Some(
+            peers
+                .iter()
+                .map(|(name, range)| (name.to_string(), range.to_string()))
+                .collect(),
+        );
+        metadata
+    }
+
+    #[test]
+    fn resolves_peers_to_what_the_dependent_provides() {
+        let resolver = Resolver::new("https://registry.npmjs.org");
+        resolver.seed(
+            "app",
+            packument(vec![metadata(
+                "app",
+                "1.0.0",
+                &[("plugin", "^1.0.0"), ("react", "^17.0.0")],
+            )]),
+        );
+        resolver.seed(
+            "plugin",
+            packument(vec![with_peers(
+                metadata("plugin", "1.0.0", &[]),
+                &[("react", ">=16")],
+            )]),
+        );
+        resolver.seed(
+            "react",
+            packument(vec![
+                metadata("react", "17.0.2", &[]),
+                metadata("react", "18.2.0", &[]),
+            ]),
+        );
+
+        let graph =
+            aw!(resolver.resolve(&[Dependency::new("app", "^1.0.0", DependencyKind::Prod)]))
+                .unwrap();
+
+        let plugin = graph.node(&PackageId::new("plugin", "1.0.0")).unwrap();
+        assert_eq!(plugin.edges[0].kind, DependencyKind::Peer);
+        assert_eq!(plugin.edges[0].range, ">=16");
+        assert_eq!(plugin.edges[0].to, PackageId::new("react", "17.0.2"));
+        assert!(!graph.contains(&PackageId::new("react", "18.2.0")));
+    }
+
+    #[test]
+    fn reports_dependents_providing_other_peers() {
+        let resolver = Resolver::new("https://registry.npmjs.org");
+        resolver.seed(
+            "a",
+            packument(vec![metadata(
+                "a",
+                "1.0.0",
+                &[("plugin", "^1.0.0"), ("react", "^17.0.0")],
+            )]),
+        );
+        resolver.seed(
+            "b",
+            packument(vec![metadata(
+                "b",
+                "1.0.0",
+                &[("plugin", "^1.0.0"), ("react", "^18.0.0")],
+            )]),
+        );
+        resolver.seed(
+            "plugin",
+            packument(vec![with_peers(
+                metadata("plugin", "1.0.0", &[]),
+                &[("react", ">=17")],
+            )]),
+        );
+        resolver.seed(
+            "react",
+            packument(vec![
+                metadata("react", "17.0.2", &[]),
+                metadata("react", "18.2.0", &[]),
+            ]),
+        );
+
+        let graph = aw!(resolver.resolve(&[
+            Dependency::new("a", "^1.0.0", DependencyKind::Prod),
+            Dependency::new("b", "^1.0.0", DependencyKind::Prod),
+        ]))
+        .unwrap();
+
+        // The plugin is shared, so b's copy of it gets a's react
+        assert_eq!(
+            crate::peer_conflicts(&graph)
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>(),
+            vec![
+                "b@1.0.0 > plugin@1.0.0 wants react@>=17 as a peer, and b@1.0.0 provides \
+                 react@18.2.0, but it gets react@17.0.2"
+            ]
+        );
+    }
+
+    #[test]
+    fn reports_missing_peers_outside_later_ranges() {
+        let resolver = Resolver::new("https://registry.npmjs.org");
+        for name in ["a", "b"] {
+            resolver.seed(
+                name,
+                packument(vec![with_peers(
+                    metadata(name, "1.0.0", &[]),
+                    &[("react", if name == "a" { "^17.0.0" } else { "^18.0.0" })],
+                )]),
+            );
+        }
+        resolver.seed(
+            "react",
+            packument(vec![
+                metadata("react", "17.0.2", &[]),
+                metadata("react", "18.2.0", &[]),
+            ]),
+        );
+
+        let graph = aw!(resolver.resolve(&[
+            Dependency::new("a", "^1.0.0", DependencyKind::Prod),
+            Dependency::new("b", "^1.0.0", DependencyKind::Prod),
+        ]))
+        .unwrap();
+
+        // Installed once, with the range of the first package wanting it
+        assert_eq!(
+            crate::peer_conflicts(&graph)
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>(),
+            vec!["b@1.0.0 wants react@^18.0.0 as a peer, but gets react@17.0.2"]
+        );
+    }
+
+    #[test]
+    fn installs_missing_peers() {
+        let resolver = Resolver::new("https://registry.npmjs.org");
+        let mut plugin = with_peers(
+            metadata("plugin", "1.0.0", &[]),
+            &[("react", "^18.0.0"), ("react-dom", "^18.0.0")],
+        );
+        plugin.peer_dependencies_meta = Some(HashMap::from([(
+            String::from("react-dom"),
+            PeerDependencyMeta { optional: true },
+        )]));
+        resolver.seed("plugin", packument(vec![plugin]));
+        resolver.seed(
+            "other",
+            packument(vec![with_peers(
+                metadata("other", "1.0.0", &[]),
+                &[("react", "*")],
+            )]),
+        );
+        resolver.seed(
+            "react",
+            packument(vec![
+                metadata("react", "17.0.2", &[]),
+                metadata("react", "18.2.0", &[]),
+            ]),
+        );
+
+        let graph = aw!(resolver.resolve(&[
+            Dependency::new("other", "^1.0.0", DependencyKind::Prod),
+            Dependency::new("plugin", "^1.0.0", DependencyKind::Prod),
+        ]))
+        .unwrap();
+
+        // Installed once for the project, and shared by every package
+        // wanting it. Optional peers nobody provides are left out
+        assert_eq!(
+            graph
+                .nodes()
+                .map(|(id, _)| id.to_string())
+                .collect::<Vec<_>>(),
+            vec!["other@1.0.0", "plugin@1.0.0", "react@18.2.0"]
+        );
+        assert_eq!(
+            graph.node(&PackageId::new("other", "1.0.0")).unwrap().edges[0].to,
+            PackageId::new("react", "18.2.0")
+        );
+    }
+
+    #[test]
+    fn leaves_out_optional_dependencies_that_fail() {
+        let (url, _) = serve(vec![response("404 Not Found", &[], "")]);
+        let mut a = metadata("a", "1.0.0", &[]);
+        a.optional_dependencies = Some(HashMap::from([(
+            String::from("missing"),
+            String::from("^1.0.0"),
+        )]));
+        let resolver = Resolver::new(&url).with_retry(RetryPolicy::none());
+        resolver.seed("a", packument(vec![a]));
+
+        let graph =
+            aw!(resolver.resolve(&[Dependency::new("a", "^1.0.0", DependencyKind::Prod)])).unwrap();
+
+        assert_eq!(graph.len(), 1);
+        assert!(graph
+            .node(&PackageId::new("a", "1.0.0"))
+            .unwrap()
+            .edges
+            .is_empty());
+    }
+
+    #[test]
+    fn resolves_overridden_dependencies_with_the_override() {
+        let manifest =
+            serde_json::from_str(r#"{ "overrides": { "b": "1.0.0", "c": { "b": "1.1.0" } } }"#)
+                .unwrap();
+        let resolver = Resolver::new("https://registry.npmjs.org")
+            .with_overrides(Overrides::from_manifest(&manifest).unwrap());
+        resolver.seed(
+            "a",
+            packument(vec![metadata("a", "1.0.0", &[("b", "^1.0.0")])]),
+        );
+        resolver.seed(
+            "c",
+            packument(vec![metadata("c", "1.0.0", &[("b", "^1.0.0")])]),
+        );
+        resolver.seed(
+            "b",
+            packument(vec![
+                metadata("b", "1.0.0", &[]),
+                metadata("b", "1.1.0", &[]),
+                metadata("b", "1.2.0", &[]),
+            ]),
+        );
+
+        let graph = aw!(resolver.resolve(&[
+            Dependency::new("a", "^1.0.0", DependencyKind::Prod),
+            Dependency::new("b", "^1.0.0", DependencyKind::Prod),
+            Dependency::new("c", "^1.0.0", DependencyKind::Prod),
+        ]))
+        .unwrap();
+
+        // The project's own dependencies aren't overridden
+        assert_eq!(graph.roots()[1].to, PackageId::new("b", "1.2.0"));
+        assert_eq!(graph.roots()[1].overridden, None);
+        let a = &graph.node(&PackageId::new("a", "1.0.0")).unwrap().edges[0];
+        assert_eq!(a.range, "^1.0.0");
+        assert_eq!(a.to, PackageId::new("b", "1.0.0"));
+        assert_eq!(a.overridden.as_deref(), Some("1.0.0"));
+        let c = &graph.node(&PackageId::new("c", "1.0.0")).unwrap().edges[0];
+        assert_eq!(c.to, PackageId::new("b", "1.1.0"));
+        assert_eq!(c.overridden.as_deref(), Some("1.1.0"));
+    }
+
+    #[test]
+    fn rejects_overrides_differing_along_paths_to_a_shared_package() {
+        let resolve = |overrides: &str| {
+            let manifest = serde_json::from_str(overrides).unwrap();
+            let resolver = Resolver::new("https://registry.npmjs.org")
+                .with_overrides(Overrides::from_manifest(&manifest).unwrap());
+            for name in ["a", "c"] {
+                resolver.seed(
+                    name,
+                    packument(vec![metadata(name, "1.0.0", &[("x", "^1.0.0")])]),
+                );
+            }
+            resolver.seed(
+                "x",
+                packument(vec![metadata("x", "1.0.0", &[("b", "^1.0.0")])]),
+            );
+            resolver.seed(
+                "b",
+                packument(vec![
+                    metadata("b", "1.1.0", &[]),
+                    metadata("b", "1.2.0", &[]),
+                ]),
+            );
+            aw!(resolver.resolve(&[
+                Dependency::new("a", "^1.0.0", DependencyKind::Prod),
+                Dependency::new("c", "^1.0.0", DependencyKind::Prod),
+            ]))
+        };
+
+        // x is shared by a and c, but only c's copy would get b@1.1.0
+        assert_eq!(
+            resolve(r#"{ "overrides": { "c": { "b": "1.1.0" } } }"#),
+            Err(Error::AmbiguousOverride {
+                name: String::from("b"),
+                first: String::from("a@1.0.0 > x@1.0.0"),
+                second: String::from("c@1.0.0 > x@1.0.0"),
+            })
+        );
+        let graph = resolve(r#"{ "overrides": { "x": { "b": "1.1.0" } } }"#).unwrap();
+        assert_eq!(
+            graph.node(&PackageId::new("x", "1.0.0")).unwrap().edges[0].to,
+            PackageId::new("b", "1.1.0")
+        );
+    }
+
+    #[test]
+    fn fails_when_something_needs_a_failed_optional_dependency() {
+        let (url, _) = serve(vec![
+            response("404 Not Found", &[], ""),
+            response("404 Not Found", &[], ""),
+        ]);
+        let mut a = metadata("a", "1.0.0", &[]);
+        a.optional_dependencies = Some(HashMap::from([(
+            String::from("missing"),
+            String::from("^1.0.0"),
+        )]));
+        let resolver = Resolver::new(&url).with_retry(RetryPolicy::none());
+        resolver.seed("a", packument(vec![a]));
+        resolver.seed(
+            "b",
+            packument(vec![metadata("b", "1.0.0", &[("c", "^1.0.0")])]),
+        );
+        resolver.seed(
+            "c",
+            packument(vec![metadata("c", "1.0.0", &[("missing", "^1.0.0")])]),
+        );
+
+        let resolved = aw!(resolver.resolve(&[
+            Dependency::new("a", "^1.0.0", DependencyKind::Prod),
+            Dependency::new("b", "^1.0.0", DependencyKind::Prod),
+        ]));
+
+        assert_eq!(
+            resolved,
+            Err(Error::PackageNotFound {
+                name: String::from("missing")
+            })
+        );
+    }
+}
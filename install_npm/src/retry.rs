@@ -0,0 +1,213 @@
+use std::time::{Duration, SystemTime};
+
+use reqwest::header::RETRY_AFTER;
+use reqwest::{RequestBuilder, Response, StatusCode};
+use tokio::sync::{Semaphore, SemaphorePermit};
+
+use crate::Error;
+
+/// How failed registry requests are retried. Timeouts, dropped
+/// connections, 5xx and 429 responses are retried with exponential
+/// backoff; everything else fails right away
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct RetryPolicy {
+    /// How many times a request is retried after its first attempt
+    pub retries: u32,
+    /// How long to wait before the first retry
+    pub min_delay: Duration,
+    /// The longest wait between two attempts, including ones asked for
+    /// with `Retry-After`
+    pub max_delay: Duration,
+    /// How much longer every wait is than the one before it
+    pub factor: u32,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            retries: 3,
+            min_delay: Duration::from_secs(1),
+            max_delay: Duration::from_secs(60),
+            factor: 2,
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Never retries, for callers that would rather fail fast
+    pub fn none() -> Self {
+        Self {
+            retries: 0,
+            ..Self::default()
+        }
+    }
+
+    /// How long to wait before retry number `attempt` (starting at 0),
+    /// unless the registry asked for something else
+    fn delay(&self, attempt: u32, retry_after: Option<Duration>) -> Duration {
+        let backoff = self
+            .min_delay
+            .saturating_mul(self.factor.saturating_pow(attempt));
+        retry_after.unwrap_or(backoff).min(self.max_delay)
+    }
+}
+
+/// Reads a `Retry-After` header, which is either a number of seconds or
+/// an HTTP date
+fn retry_after(response: &Response) -> Option<Duration> {
+    let value = response.headers().get(RETRY_AFTER)?.to_str().ok()?.trim();
+    match value.parse::<u64>() {
+        Ok(seconds) => Some(Duration::from_secs(seconds)),
+        Err(_) => httpdate::parse_http_date(value)
+            .ok()?
+            .duration_since(SystemTime::now())
+            .ok(),
+    }
+}
+
+/// The message of `error` followed by those of its sources, as reqwest
+/// keeps the interesting part (like "connection refused") in the sources
+pub(crate) fn cause(error: &dyn std::error::Error) -> String {
+    let mut cause = error.to_string();
+    let mut source = error.source();
+    while let Some(error) = source {
+        cause.push_str(": ");
+        cause.push_str(&error.to_string());
+        source = error.source();
+    }
+    cause
+}
+
+/// Sends the request built by `request` until it gets a response worth
+/// handing back, retrying according to `policy`. `name` is the package the
+/// request is for, so errors can say which one failed.
+///
+/// With a `limit`, every attempt waits for a permit. The permit comes back
+/// with the response, so the body is read under it too, but it isn't held
+/// while backing off, leaving the slot to other requests
+pub(crate) async fn send<'a>(
+    request: impl Fn() -> RequestBuilder,
+    name: &str,
+    url: &str,
+    policy: &RetryPolicy,
+    limit: Option<&'a Semaphore>,
+) -> Result<(Response, Option<SemaphorePermit<'a>>), Error> {
+    let mut attempt = 0;
+    loop {
+        let permit = match limit {
+            Some(limit) => Some(limit.acquire().await.unwrap()),
+            None => None,
+        };
+        let (error, wait) = match request().send().await {
+            Ok(response)
+                if response.status() == StatusCode::TOO_MANY_REQUESTS
+                    || response.status().is_server_error() =>
+            {
+                let wait = retry_after(&response);
+                let error = Error::HttpStatus {
+                    name: name.to_string(),
+                    url: url.to_string(),
+                    status: response.status().as_u16(),
+                };
+                (error, wait)
+            }
+            Ok(response) => return Ok((response, permit)),
+            Err(e) => {
+                let error = Error::Network {
+                    name: name.to_string(),
+                    url: url.to_string(),
+                    cause: cause(&e),
+                };
+                if !e.is_timeout() && !e.is_connect() && !e.is_request() {
+                    return Err(error);
+                }
+                (error, None)
+            }
+        };
+
+        if attempt >= policy.retries {
+            return Err(error);
+        }
+        drop(permit);
+        tokio::time::sleep(policy.delay(attempt, wait)).await;
+        attempt += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{response, serve};
+    use crate::CLIENT;
+
+    fn instant() -> RetryPolicy {
+        RetryPolicy {
+            min_delay: Duration::ZERO,
+            ..RetryPolicy::default()
+        }
+    }
+
+    #[test]
+    fn backs_off_exponentially() {
+        let policy = RetryPolicy::default();
+        assert_eq!(policy.delay(0, None), Duration::from_secs(1));
+        assert_eq!(policy.delay(3, None), Duration::from_secs(8));
+        assert_eq!(policy.delay(10, None), Duration::from_secs(60));
+        assert_eq!(
+            policy.delay(0, Some(Duration::from_secs(5))),
+            Duration::from_secs(5)
+        );
+        assert_eq!(
+            policy.delay(0, Some(Duration::from_secs(3600))),
+            Duration::from_secs(60)
+        );
+    }
+
+    #[test]
+    fn retries_server_errors() {
+        let (url, requests) = serve(vec![
+            response("503 Service Unavailable", &[], ""),
+            response("429 Too Many Requests", &[("retry-after", "0")], ""),
+            response("200 OK", &[], "ok"),
+        ]);
+
+        let response = tokio_test::block_on(send(|| CLIENT.get(&url), "a", &url, &instant(), None));
+
+        assert_eq!(response.unwrap().0.status(), StatusCode::OK);
+        assert_eq!(requests.join().unwrap().len(), 3);
+    }
+
+    #[test]
+    fn gives_up_after_retries() {
+        let (url, requests) = serve(vec![
+            response("500 Internal Server Error", &[], ""),
+            response("502 Bad Gateway", &[], ""),
+        ]);
+        let policy = RetryPolicy {
+            retries: 1,
+            ..instant()
+        };
+
+        let response = tokio_test::block_on(send(|| CLIENT.get(&url), "a", &url, &policy, None));
+
+        assert_eq!(
+            response.unwrap_err(),
+            Error::HttpStatus {
+                name: String::from("a"),
+                url: url.clone(),
+                status: 502,
+            }
+        );
+        assert_eq!(requests.join().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn does_not_retry_client_errors() {
+        let (url, requests) = serve(vec![response("404 Not Found", &[], "")]);
+
+        let response = tokio_test::block_on(send(|| CLIENT.get(&url), "a", &url, &instant(), None));
+
+        assert_eq!(response.unwrap().0.status(), StatusCode::NOT_FOUND);
+        assert_eq!(requests.join().unwrap().len(), 1);
+    }
+}
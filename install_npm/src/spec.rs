@@ -0,0 +1,291 @@
+use std::fmt::{self, Display};
+use std::path::PathBuf;
+
+use crate::{Error, VersionSpec};
+
+/// Which commit of a git repository a dependency asks for
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub enum GitReference {
+    /// The default branch
+    Head,
+    /// A branch, tag or commit (`#v1.0.0`)
+    Committish(String),
+    /// The highest tag satisfying a range (`#semver:^1.0.0`)
+    Semver(String),
+}
+
+/// A git repository and the commit of it to install
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub struct GitSpec {
+    /// A URL the `git` binary can clone
+    pub url: String,
+    pub reference: GitReference,
+}
+
+impl GitSpec {
+    fn parse(url: &str) -> Self {
+        let (url, reference) = match url.split_once('#') {
+            Some((url, reference)) => (url, reference),
+            None => (url, ""),
+        };
+        let reference = match reference.strip_prefix("semver:") {
+            Some(range) => GitReference::Semver(range.to_string()),
+            None if reference.is_empty() => GitReference::Head,
+            None => GitReference::Committish(reference.to_string()),
+        };
+
+        Self {
+            url: url.strip_prefix("git+").unwrap_or(url).to_string(),
+            reference,
+        }
+    }
+}
+
+/// Where a dependency comes from, parsed from its package.json value the way
+/// npm (and, for `link:` and `workspace:`, pnpm and Yarn) reads it
+#[derive(PartialEq, Debug, Clone)]
+pub enum Spec {
+    /// `^1.0.0`, `latest`: a package from the registry
+    Registry(VersionSpec),
+    /// `npm:other@^1.0.0`: another registry package, installed under the
+    /// name of the dependency
+    Alias { name: String, version: VersionSpec },
+    /// `file:../a` or `../a`: a package directory on disk
+    Directory(PathBuf),
+    /// `file:a.tgz`: a package tarball on disk
+    LocalTarball(PathBuf),
+    /// `link:../a`: a directory symlinked into `node_modules` as is
+    Link(PathBuf),
+    /// `workspace:^`: a package of the same workspace
+    Workspace(String),
+    /// `git+https://…`, `github:user/repo`, `user/repo#v1.0.0`
+    Git(GitSpec),
+    /// `https://…/a.tgz`: a tarball downloaded from anywhere
+    RemoteTarball(String),
+}
+
+impl Display for Spec {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Spec::Registry(_) => write!(f, "registry package"),
+            Spec::Alias { .. } => write!(f, "aliased package"),
+            Spec::Directory(_) | Spec::LocalTarball(_) => write!(f, "local package"),
+            Spec::Link(_) => write!(f, "linked package"),
+            Spec::Workspace(_) => write!(f, "workspace package"),
+            Spec::Git(_) => write!(f, "git dependency"),
+            Spec::RemoteTarball(_) => write!(f, "tarball URL"),
+        }
+    }
+}
+
+fn is_tarball_path(path: &str) -> bool {
+    [".tgz", ".tar.gz", ".tar"]
+        .iter()
+        .any(|extension| path.ends_with(extension))
+}
+
+/// `user/repo`, optionally followed by `#committish`. Names can't start
+/// with `.` or `@`, which tells these apart from paths and scoped names
+fn is_github_shorthand(spec: &str) -> bool {
+    let repo = spec.split('#').next().unwrap_or(spec);
+    match repo.split_once('/') {
+        Some((user, repo)) => {
+            let valid = |part: &str| {
+                !part.is_empty()
+                    && !part.contains([':', '@', '%', '/'])
+                    && !part.chars().any(char::is_whitespace)
+            };
+            valid(user) && valid(repo) && !user.starts_with(['.', '-'])
+        }
+        None => false,
+    }
+}
+
+/// The HTTPS clone URL of a repository on a known git host
+fn hosted_url(host: &str, repo: &str) -> String {
+    let repo = repo.trim_end_matches(".git");
+    format!("https://{host}/{repo}.git")
+}
+
+impl Spec {
+    /// Parses the spec of the dependency called `name`. Like npm, anything
+    /// that isn't recognizably a path, URL or git repository is a registry
+    /// range or tag
+    pub fn parse(name: &str, spec: &str) -> Result<Self, Error> {
+        let invalid = || Error::InvalidSpec {
+            name: name.to_string(),
+            spec: spec.to_string(),
+        };
+
+        if let Some(aliased) = spec.strip_prefix("npm:") {
+            // The `@` of a scope isn't the one before the range. Package
+            // names never start with a multi-byte character
+            let rest = aliased.get(1..).ok_or_else(invalid)?;
+            let (name, version) = match rest.find('@') {
+                Some(index) => (&aliased[..index + 1], &aliased[index + 2..]),
+                None => (aliased, "latest"),
+            };
+            return Ok(Spec::Alias {
+                name: name.to_string(),
+                version: VersionSpec::parse(version).map_err(|_| invalid())?,
+            });
+        }
+        if let Some(path) = spec.strip_prefix("link:") {
+            return Ok(Spec::Link(PathBuf::from(path)));
+        }
+        if let Some(range) = spec.strip_prefix("workspace:") {
+            return Ok(Spec::Workspace(range.to_string()));
+        }
+
+        let path = spec.strip_prefix("file:").or_else(|| {
+            ["./", "../", "/", "~/"]
+                .iter()
+                .any(|prefix| spec.starts_with(prefix))
+                .then_some(spec)
+        });
+        if let Some(path) = path {
+            return Ok(if is_tarball_path(path) {
+                Spec::LocalTarball(PathBuf::from(path))
+            } else {
+                Spec::Directory(PathBuf::from(path))
+            });
+        }
+
+        for (prefix, host) in [
+            ("github:", "github.com"),
+            ("gitlab:", "gitlab.com"),
+            ("bitbucket:", "bitbucket.org"),
+        ] {
+            if let Some(repo) = spec.strip_prefix(prefix) {
+                let mut git = GitSpec::parse(repo);
+                git.url = hosted_url(host, &git.url);
+                return Ok(Spec::Git(git));
+            }
+        }
+        if spec.starts_with("git+") || spec.starts_with("git://") || spec.starts_with("git@") {
+            return Ok(Spec::Git(GitSpec::parse(spec)));
+        }
+        if spec.starts_with("http://") || spec.starts_with("https://") {
+            let url = spec.split('#').next().unwrap_or(spec);
+            return Ok(if url.ends_with(".git") {
+                Spec::Git(GitSpec::parse(spec))
+            } else {
+                Spec::RemoteTarball(spec.to_string())
+            });
+        }
+        if is_github_shorthand(spec) {
+            let mut git = GitSpec::parse(spec);
+            git.url = hosted_url("github.com", &git.url);
+            return Ok(Spec::Git(git));
+        }
+
+        VersionSpec::parse(spec)
+            .map(Spec::Registry)
+            .map_err(|_| invalid())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use node_semver::Range;
+
+    fn parse(spec: &str) -> Spec {
+        Spec::parse("a", spec).unwrap()
+    }
+
+    fn git(url: &str, reference: GitReference) -> Spec {
+        Spec::Git(GitSpec {
+            url: url.to_string(),
+            reference,
+        })
+    }
+
+    #[test]
+    fn parses_registry_specs() {
+        assert_eq!(
+            parse("^1.0.0"),
+            Spec::Registry(VersionSpec::Range(Range::parse("^1.0.0").unwrap()))
+        );
+        assert_eq!(
+            parse("next"),
+            Spec::Registry(VersionSpec::Tag(String::from("next")))
+        );
+        assert_eq!(
+            parse("npm:@scope/b@^2.0.0"),
+            Spec::Alias {
+                name: String::from("@scope/b"),
+                version: VersionSpec::Range(Range::parse("^2.0.0").unwrap())
+            }
+        );
+        assert_eq!(
+            parse("npm:b"),
+            Spec::Alias {
+                name: String::from("b"),
+                version: VersionSpec::Tag(String::from("latest"))
+            }
+        );
+        for spec in ["npm:", "npm:é", "npm:é@1"] {
+            assert_eq!(
+                Spec::parse("a", spec),
+                Err(Error::InvalidSpec {
+                    name: String::from("a"),
+                    spec: spec.to_string()
+                })
+            );
+        }
+        assert_eq!(
+            Spec::parse("a", "not a range"),
+            Err(Error::InvalidSpec {
+                name: String::from("a"),
+                spec: String::from("not a range")
+            })
+        );
+    }
+
+    #[test]
+    fn parses_local_specs() {
+        assert_eq!(parse("file:../b"), Spec::Directory(PathBuf::from("../b")));
+        assert_eq!(parse("./b"), Spec::Directory(PathBuf::from("./b")));
+        assert_eq!(
+            parse("file:vendor/b-1.0.0.tgz"),
+            Spec::LocalTarball(PathBuf::from("vendor/b-1.0.0.tgz"))
+        );
+        assert_eq!(parse("link:../b"), Spec::Link(PathBuf::from("../b")));
+        assert_eq!(parse("workspace:^"), Spec::Workspace(String::from("^")));
+    }
+
+    #[test]
+    fn parses_git_specs() {
+        assert_eq!(
+            parse("git+ssh://git@github.com/user/repo.git#v1.0.0"),
+            git(
+                "ssh://git@github.com/user/repo.git",
+                GitReference::Committish(String::from("v1.0.0"))
+            )
+        );
+        assert_eq!(
+            parse("user/repo#semver:^1.0.0"),
+            git(
+                "https://github.com/user/repo.git",
+                GitReference::Semver(String::from("^1.0.0"))
+            )
+        );
+        assert_eq!(
+            parse("gitlab:user/repo"),
+            git("https://gitlab.com/user/repo.git", GitReference::Head)
+        );
+        assert_eq!(
+            parse("https://example.com/repo.git"),
+            git("https://example.com/repo.git", GitReference::Head)
+        );
+    }
+
+    #[test]
+    fn parses_tarball_urls() {
+        assert_eq!(
+            parse("https://example.com/a-1.0.0.tgz"),
+            Spec::RemoteTarball(String::from("https://example.com/a-1.0.0.tgz"))
+        );
+    }
+}
@@ -0,0 +1,394 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha512};
+use tempdir::TempDir;
+
+use crate::extract::{files, stage, File};
+use crate::local::{self, Local};
+use crate::{
+    dist_integrity, download, git, verify, Cache, Error, Metadata, NetworkMode, Npmrc, RetryPolicy,
+};
+
+fn is_false(value: &bool) -> bool {
+    !value
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+/// A file of a package, and the content hash it is stored under
+#[derive(Serialize, Deserialize, PartialEq, Eq, Debug, Clone)]
+struct IndexedFile {
+    hash: String,
+    #[serde(default, skip_serializing_if = "is_false")]
+    executable: bool,
+}
+
+/// Every file of a package, keyed by its path inside the package
+#[derive(Serialize, Deserialize, PartialEq, Eq, Debug, Clone, Default)]
+struct Index {
+    files: BTreeMap<String, IndexedFile>,
+}
+
+/// A content-addressable store of package files, shared by every project
+/// on the machine (usually `~/.vortex/store`).
+///
+/// Files are stored once under the sha512 of their contents, and packages
+/// are installed by hard-linking those files into `node_modules`, so ten
+/// projects depending on the same package only cost one copy of it. Each
+/// package also gets an index, keyed by its integrity, listing its files,
+/// so installing it again doesn't even need the tarball
+#[derive(Clone, Debug)]
+pub struct Store {
+    root: PathBuf,
+    mode: NetworkMode,
+    cache: Option<Cache>,
+    npmrc: Npmrc,
+    retry: RetryPolicy,
+}
+
+impl Store {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self {
+            root: root.into(),
+            mode: NetworkMode::default(),
+            cache: None,
+            npmrc: Npmrc::default(),
+            retry: RetryPolicy::default(),
+        }
+    }
+
+    /// With [NetworkMode::Offline], packages missing from the store are an
+    /// error instead of being downloaded. Packages the store has are always
+    /// installed from it
+    pub fn with_network_mode(mut self, mode: NetworkMode) -> Self {
+        self.mode = mode;
+        self
+    }
+
+    /// Installs git dependencies from the tarballs the [crate::Resolver]
+    /// packed them into in `cache`
+    pub fn with_cache(mut self, cache: Cache) -> Self {
+        self.cache = Some(cache);
+        self
+    }
+
+    /// Sends the credentials configured in `npmrc` with tarball downloads
+    pub fn with_npmrc(mut self, npmrc: Npmrc) -> Self {
+        self.npmrc = npmrc;
+        self
+    }
+
+    /// How failed tarball downloads are retried
+    pub fn with_retry(mut self, retry: RetryPolicy) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    fn file_path(&self, file: &IndexedFile) -> PathBuf {
+        // Permissions are shared by hard links, so executables get a copy
+        // of their own
+        let suffix = if file.executable { "-exec" } else { "" };
+        self.root
+            .join("files")
+            .join(&file.hash[..2])
+            .join(format!("{}{suffix}", &file.hash[2..]))
+    }
+
+    /// `index/sha512/ab/cdef….json` for `sha512-q80=…`
+    fn index_path(&self, integrity: &str) -> Option<PathBuf> {
+        let (algorithm, digest) = integrity.split_whitespace().next()?.split_once('-')?;
+        let digest = hex(&base64::decode(digest).ok()?);
+        if digest.len() < 3 {
+            return None;
+        }
+
+        Some(
+            self.root
+                .join("index")
+                .join(algorithm)
+                .join(&digest[..2])
+                .join(format!("{}.json", &digest[2..])),
+        )
+    }
+
+    /// The index of the package with `integrity`, if every one of its files
+    /// is still in the store
+    fn read_index(&self, integrity: &str) -> Option<Index> {
+        let contents = fs::read_to_string(self.index_path(integrity)?).ok()?;
+        let index: Index = serde_json::from_str(&contents).ok()?;
+
+        if index
+            .files
+            .values()
+            .all(|file| self.file_path(file).is_file())
+        {
+            Some(index)
+        } else {
+            None
+        }
+    }
+
+    fn write_index(&self, integrity: &str, index: &Index) -> Result<(), Error> {
+        let path = match self.index_path(integrity) {
+            Some(path) => path,
+            None => return Ok(()),
+        };
+        self.write(
+            &path,
+            serde_json::to_string(index).unwrap().as_bytes(),
+            false,
+        )
+    }
+
+    /// Writes `contents` to a temporary file first, so other installs never
+    /// see a partially written file
+    fn write(&self, path: &Path, contents: &[u8], executable: bool) -> Result<(), Error> {
+        let tmp = self.root.join("tmp");
+        fs::create_dir_all(&tmp).map_err(|_| Error::IoError)?;
+        let staging = TempDir::new_in(&tmp, "write").map_err(|_| Error::IoError)?;
+        let staged = staging.path().join("file");
+        fs::write(&staged, contents).map_err(|_| Error::IoError)?;
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = if executable { 0o755 } else { 0o644 };
+            fs::set_permissions(&staged, fs::Permissions::from_mode(mode))
+                .map_err(|_| Error::IoError)?;
+        }
+        #[cfg(not(unix))]
+        let _ = executable;
+
+        fs::create_dir_all(path.parent().ok_or(Error::IoError)?).map_err(|_| Error::IoError)?;
+        fs::rename(&staged, path).map_err(|_| Error::IoError)
+    }
+
+    /// Adds files to the store, skipping the ones it already has
+    fn add(&self, files: Vec<File>) -> Result<Index, Error> {
+        let mut index = Index::default();
+        for file in files {
+            let indexed = IndexedFile {
+                hash: hex(&Sha512::digest(&file.contents)),
+                executable: file.executable,
+            };
+
+            let path = self.file_path(&indexed);
+            if !path.is_file() {
+                self.write(&path, &file.contents, file.executable)?;
+            }
+
+            let key = file
+                .path
+                .iter()
+                .map(|part| part.to_string_lossy())
+                .collect::<Vec<_>>()
+                .join("/");
+            index.files.insert(key, indexed);
+        }
+        Ok(index)
+    }
+
+    /// Fills `destination` with hard links to the files of `index`, copying
+    /// them instead when the store is on another filesystem
+    fn link(&self, index: &Index, destination: &Path) -> Result<(), Error> {
+        stage(destination, |staging| {
+            for (path, file) in &index.files {
+                let target = path
+                    .split('/')
+                    .fold(staging.to_path_buf(), |target, part| target.join(part));
+                if let Some(parent) = target.parent() {
+                    fs::create_dir_all(parent).map_err(|_| Error::IoError)?;
+                }
+
+                let source = self.file_path(file);
+                match fs::hard_link(&source, &target) {
+                    Ok(()) => {}
+                    Err(e) if e.kind() == ErrorKind::AlreadyExists => {}
+                    Err(_) => {
+                        fs::copy(&source, &target).map_err(|_| Error::IoError)?;
+                    }
+                }
+            }
+            Ok(())
+        })
+    }
+
+    /// Adds an already downloaded package tarball to the store, so
+    /// installing the package with `integrity` doesn't download it again
+    pub(crate) fn insert(&self, integrity: &str, tarball: &[u8]) -> Result<(), Error> {
+        let index = self.add(files(tarball)?)?;
+        self.write_index(integrity, &index)
+    }
+
+    /// Installs a resolved package into `destination` from the store,
+    /// downloading (and verifying) it first if the store doesn't have it yet
+    pub async fn install(&self, metadata: &Metadata, destination: &Path) -> Result<(), Error> {
+        let integrity = dist_integrity(&metadata.dist);
+        if let Some(index) = integrity
+            .as_deref()
+            .and_then(|integrity| self.read_index(integrity))
+        {
+            return self.link(&index, destination);
+        }
+        let not_cached = || Error::NotCached {
+            name: metadata.name.clone(),
+        };
+
+        let resolved = &metadata.dist.tarball;
+        // Git dependencies were packed while resolving them
+        let tarball = if let Some((_, sha)) = git::parse_resolved(&metadata.name, resolved) {
+            self.cache
+                .as_ref()
+                .and_then(|cache| cache.read_git(&sha))
+                .ok_or_else(not_cached)?
+        } else if let Some(local) = local::parse_resolved(&metadata.name, resolved) {
+            if let Local::Link(target) = local {
+                return local::link(&metadata.name, &target, destination);
+            }
+            let tarball = local.tarball(&metadata.name)?;
+            // Directories are packed again on every install, so only
+            // tarballs have an integrity to check
+            if let Local::Tarball(_) = local {
+                verify(&metadata.name, &metadata.dist, &tarball)?;
+            }
+            tarball
+        } else if self.mode == NetworkMode::Offline {
+            return Err(not_cached());
+        } else {
+            let tarball = download(metadata, &self.npmrc, &self.retry).await?;
+            verify(&metadata.name, &metadata.dist, &tarball)?;
+            tarball
+        };
+
+        let index = self.add(files(&tarball)?)?;
+        if let Some(integrity) = integrity {
+            self.write_index(&integrity, &index)?;
+        }
+        self.link(&index, destination)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::extract::tests::tarball;
+    use crate::sha512_integrity;
+
+    fn package() -> Vec<u8> {
+        tarball(&[
+            ("package/package.json", "{\"name\":\"a\"}"),
+            ("package/lib/index.js", "module.exports = 1"),
+        ])
+    }
+
+    #[test]
+    fn links_files_from_the_store() {
+        let dir = TempDir::new("vortex-store").unwrap();
+        let store = Store::new(dir.path().join("store"));
+        let index = store.add(files(&package()).unwrap()).unwrap();
+
+        let first = dir.path().join("one").join("node_modules").join("a");
+        let second = dir.path().join("two").join("node_modules").join("a");
+        store.link(&index, &first).unwrap();
+        store.link(&index, &second).unwrap();
+
+        assert_eq!(
+            fs::read_to_string(second.join("lib").join("index.js")).unwrap(),
+            "module.exports = 1"
+        );
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::MetadataExt;
+            let inode = |path: &Path| fs::metadata(path).unwrap().ino();
+            assert_eq!(
+                inode(&first.join("package.json")),
+                inode(&second.join("package.json"))
+            );
+        }
+    }
+
+    #[test]
+    fn indexes_by_integrity() {
+        let dir = TempDir::new("vortex-store").unwrap();
+        let store = Store::new(dir.path());
+        let tarball = package();
+        let integrity = sha512_integrity(&tarball);
+
+        assert_eq!(store.read_index(&integrity), None);
+        let index = store.add(files(&tarball).unwrap()).unwrap();
+        store.write_index(&integrity, &index).unwrap();
+        assert_eq!(store.read_index(&integrity), Some(index.clone()));
+
+        // An index is useless once one of its files is gone
+        fs::remove_file(store.file_path(&index.files["lib/index.js"])).unwrap();
+        assert_eq!(store.read_index(&integrity), None);
+    }
+
+    #[test]
+    fn offline_needs_an_index() {
+        let dir = TempDir::new("vortex-store").unwrap();
+        let store = Store::new(dir.path()).with_network_mode(NetworkMode::Offline);
+        let tarball = package();
+        let mut metadata = crate::tests::metadata("a", "1.0.0", &[]);
+        metadata.dist.integrity = Some(sha512_integrity(&tarball));
+        let destination = dir.path().join("node_modules").join("a");
+
+        assert_eq!(
+            tokio_test::block_on(store.install(&metadata, &destination)),
+            Err(Error::NotCached {
+                name: String::from("a")
+            })
+        );
+
+        let index = store.add(files(&tarball).unwrap()).unwrap();
+        store
+            .write_index(metadata.dist.integrity.as_ref().unwrap(), &index)
+            .unwrap();
+        tokio_test::block_on(store.install(&metadata, &destination)).unwrap();
+        assert!(destination.join("package.json").is_file());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn keeps_executables_apart() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = TempDir::new("vortex-store").unwrap();
+        let store = Store::new(dir.path());
+        let index = store
+            .add(vec![
+                File {
+                    path: PathBuf::from("bin/cli.js"),
+                    contents: b"#!/usr/bin/env node".to_vec(),
+                    executable: true,
+                },
+                File {
+                    path: PathBuf::from("cli.js"),
+                    contents: b"#!/usr/bin/env node".to_vec(),
+                    executable: false,
+                },
+            ])
+            .unwrap();
+
+        let mode = |file: &IndexedFile| {
+            fs::metadata(store.file_path(file))
+                .unwrap()
+                .permissions()
+                .mode()
+                & 0o777
+        };
+        assert_eq!(mode(&index.files["bin/cli.js"]), 0o755);
+        assert_eq!(mode(&index.files["cli.js"]), 0o644);
+    }
+}
@@ -0,0 +1,265 @@
+use std::collections::HashMap;
+
+use crate::import::{split_key, unmappable, Pins};
+use crate::{Dependency, Dist, Error, Import, Metadata, PackageId, Platform, Unmapped};
+
+/// The lockfile of Yarn classic
+pub const YARN_LOCK_NAME: &str = "yarn.lock";
+
+/// One `"a@^1.0.0", "a@^1.1.0":` block of a yarn.lock
+#[derive(Default, Debug)]
+struct Entry {
+    keys: Vec<String>,
+    fields: HashMap<String, String>,
+    dependencies: HashMap<String, String>,
+    optional_dependencies: HashMap<String, String>,
+}
+
+fn unquote(value: &str) -> &str {
+    value
+        .strip_prefix('"')
+        .and_then(|value| value.strip_suffix('"'))
+        .unwrap_or(value)
+}
+
+/// Splits a `key value` line, where either side may be quoted
+fn split_pair(line: &str) -> Option<(&str, &str)> {
+    let (key, value) = match line.strip_prefix('"') {
+        Some(rest) => {
+            let end = rest.find('"')?;
+            (&rest[..end], rest[end + 1..].trim_start())
+        }
+        None => line.split_once(' ')?,
+    };
+    Some((unquote(key), unquote(value.trim())))
+}
+
+/// Parses the indentation-based format of yarn.lock v1. Yarn 2+ writes
+/// YAML with a `__metadata` block instead, which isn't supported
+fn parse(contents: &str) -> Result<Vec<Entry>, Error> {
+    let mut entries: Vec<Entry> = Vec::new();
+    // The dependency map the lines being read belong to
+    let mut section: Option<&str> = None;
+
+    for line in contents.lines() {
+        let trimmed = line.trim();
+        if trimmed.is_empty() || trimmed.starts_with('#') {
+            continue;
+        }
+        if trimmed.starts_with("__metadata:") {
+            return Err(Error::InvalidLockfile);
+        }
+
+        let indent = line.len() - line.trim_start().len();
+        match indent {
+            0 => {
+                let keys = trimmed.strip_suffix(':').ok_or(Error::InvalidLockfile)?;
+                entries.push(Entry {
+                    keys: keys
+                        .split(", ")
+                        .map(|key| unquote(key.trim()).to_string())
+                        .collect(),
+                    ..Entry::default()
+                });
+            }
+            2 => {
+                let entry = entries.last_mut().ok_or(Error::InvalidLockfile)?;
+                if let Some(map) = trimmed.strip_suffix(':') {
+                    section = Some(map)
+                        .filter(|map| *map == "dependencies" || *map == "optionalDependencies");
+                    continue;
+                }
+                let (key, value) = split_pair(trimmed).ok_or(Error::InvalidLockfile)?;
+                entry.fields.insert(key.to_string(), value.to_string());
+            }
+            4 if section.is_some() => {
+                let entry = entries.last_mut().ok_or(Error::InvalidLockfile)?;
+                let (name, range) = split_pair(trimmed).ok_or(Error::InvalidLockfile)?;
+                let dependencies = match section {
+                    Some("optionalDependencies") => &mut entry.optional_dependencies,
+                    _ => &mut entry.dependencies,
+                };
+                dependencies.insert(name.to_string(), range.to_string());
+            }
+            _ => {}
+        }
+    }
+
+    Ok(entries)
+}
+
+/// Converts a Yarn classic `yarn.lock` into a [crate::Graph] rooted at
+/// `dependencies` (usually [crate::Manifest::all_dependencies]). Every
+/// package keeps the tarball and integrity Yarn pinned it to
+pub fn import_yarn_lock(contents: &str, dependencies: &[Dependency]) -> Result<Import, Error> {
+    let mut pins = Pins::default();
+
+    for entry in parse(contents)? {
+        let specs: Vec<(&str, &str)> = entry
+            .keys
+            .iter()
+            .map(|key| split_key(key).ok_or(Error::InvalidLockfile))
+            .collect::<Result<_, _>>()?;
+        let name = match specs.first() {
+            Some((name, _)) => name.to_string(),
+            None => continue,
+        };
+
+        let reason = specs.iter().find_map(|(_, spec)| unmappable(spec));
+        let (version, resolved) = match (entry.fields.get("version"), entry.fields.get("resolved"))
+        {
+            (Some(version), Some(resolved)) if reason.is_none() => (version, resolved),
+            _ => {
+                pins.unmapped
+                    .extend(specs.iter().map(|(name, spec)| Unmapped {
+                        name: name.to_string(),
+                        spec: spec.to_string(),
+                        reason: reason.unwrap_or("no resolved tarball"),
+                    }));
+                continue;
+            }
+        };
+
+        // Yarn appends the sha1 of the tarball to its URL
+        let (tarball, shasum) = resolved.split_once('#').unwrap_or((resolved, ""));
+        let metadata = Metadata {
+            name,
+            version: version.clone(),
+            dependencies: Some(entry.dependencies),
+            peer_dependencies: None,
+            peer_dependencies_meta: None,
+            optional_dependencies: Some(entry.optional_dependencies),
+            platform: Platform::default(),
+            dist: Dist {
+                tarball: tarball.to_string(),
+                shasum: shasum.to_string(),
+                integrity: entry.fields.get("integrity").cloned(),
+            },
+        };
+
+        let id = PackageId::from(&metadata);
+        for (name, spec) in specs {
+            pins.requests
+                .insert((name.to_string(), spec.to_string()), id.clone());
+        }
+        pins.packages.entry(id).or_insert(metadata);
+    }
+
+    let roots: Vec<(Dependency, String)> = dependencies
+        .iter()
+        .map(|dependency| (dependency.clone(), dependency.range.clone()))
+        .collect();
+    Ok(pins.into_import(&roots))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{DependencyKind, Lockfile};
+
+    const LOCK: &str = r#"# THIS IS AN AUTOGENERATED FILE. DO NOT EDIT THIS FILE DIRECTLY.
+# yarn lockfile v1
+
+
+"@scope/b@^1.0.0", "@scope/b@^1.1.0":
+  version "1.2.0"
+  resolved "https://registry.yarnpkg.com/@scope/b/-/b-1.2.0.tgz#0123456789abcdef0123456789abcdef01234567"
+  integrity sha512-bbbb
+
+a@^1.0.0:
+  version "1.0.0"
+  resolved "https://registry.yarnpkg.com/a/-/a-1.0.0.tgz#89abcdef0123456789abcdef0123456789abcdef"
+  integrity sha512-aaaa
+  dependencies:
+    "@scope/b" "^1.1.0"
+  optionalDependencies:
+    c "c/c#main"
+
+"c@c/c#main":
+  version "0.0.0"
+  resolved "https://codeload.github.com/c/c/tar.gz/0123456"
+"#;
+
+    #[test]
+    fn parses_entries() {
+        let entries = parse(LOCK).unwrap();
+        assert_eq!(entries.len(), 3);
+        assert_eq!(entries[0].keys, vec!["@scope/b@^1.0.0", "@scope/b@^1.1.0"]);
+        assert_eq!(entries[1].fields["integrity"], "sha512-aaaa");
+        assert_eq!(entries[1].dependencies["@scope/b"], "^1.1.0");
+        assert_eq!(entries[1].optional_dependencies["c"], "c/c#main");
+        assert!(!entries[1].dependencies.contains_key("c"));
+    }
+
+    #[test]
+    fn imports_pinned_versions() {
+        let import = import_yarn_lock(
+            LOCK,
+            &[Dependency::new("@scope/b", "^1.0.0", DependencyKind::Prod)],
+        )
+        .unwrap();
+
+        let b = PackageId::new("@scope/b", "1.2.0");
+        assert_eq!(import.graph.roots()[0].to, b);
+        let dist = &import.graph.node(&b).unwrap().metadata.dist;
+        assert_eq!(
+            dist.tarball,
+            "https://registry.yarnpkg.com/@scope/b/-/b-1.2.0.tgz"
+        );
+        assert_eq!(dist.shasum, "0123456789abcdef0123456789abcdef01234567");
+        assert_eq!(dist.integrity.as_deref(), Some("sha512-bbbb"));
+    }
+
+    #[test]
+    fn reports_git_dependencies() {
+        let import = import_yarn_lock(
+            LOCK,
+            &[Dependency::new("a", "^1.0.0", DependencyKind::Prod)],
+        )
+        .unwrap();
+
+        assert!(import.graph.roots().is_empty());
+        assert_eq!(import.dropped, vec![PackageId::new("a", "1.0.0")]);
+        assert_eq!(
+            import.unmapped,
+            vec![Unmapped {
+                name: String::from("c"),
+                spec: String::from("c/c#main"),
+                reason: "git dependency",
+            }]
+        );
+    }
+
+    #[test]
+    fn keeps_optional_dependencies_optional() {
+        let import = import_yarn_lock(
+            r#"
+a@^1.0.0:
+  version "1.0.0"
+  resolved "https://registry.yarnpkg.com/a/-/a-1.0.0.tgz"
+  optionalDependencies:
+    b "^1.0.0"
+
+b@^1.0.0:
+  version "1.0.0"
+  resolved "https://registry.yarnpkg.com/b/-/b-1.0.0.tgz"
+"#,
+            &[Dependency::new("a", "^1.0.0", DependencyKind::Prod)],
+        )
+        .unwrap();
+
+        let a = import.graph.node(&PackageId::new("a", "1.0.0")).unwrap();
+        assert_eq!(a.edges[0].kind, DependencyKind::Optional);
+        let lockfile = Lockfile::from_graph(&import.graph);
+        assert!(!lockfile.packages["a@1.0.0"].optional);
+        assert!(lockfile.packages["b@1.0.0"].optional);
+    }
+
+    #[test]
+    fn rejects_yarn_berry() {
+        assert_eq!(
+            parse("__metadata:\n  version: 6\n").unwrap_err(),
+            Error::InvalidLockfile
+        );
+    }
+}
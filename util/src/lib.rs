@@ -1,11 +1,11 @@
 pub fn unroll_version(version: &str) -> String {
     match version.parse::<i32>() {
         // Major
-        Ok(n) => return format!("{}.0.0", n),
+        Ok(n) => format!("{}.0.0", n),
         _ => {
             // Only major + minor
             match version.parse::<f32>() {
-                Ok(n) => return format!("{}.0", n),
+                Ok(n) => format!("{}.0", n),
                 _ => String::from(version)
             }
         }
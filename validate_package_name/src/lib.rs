@@ -83,7 +83,7 @@ pub fn validate(name: &str) -> Result<(), &str> {
         return Err("Package name cannot start with a period/underscore")
     }
 
-    if SPECIAL_CHARACTER_REGEX.is_match(name.split('/').last().unwrap()) {
+    if SPECIAL_CHARACTER_REGEX.is_match(name.split('/').next_back().unwrap()) {
         return Err("Package name cannot contain special characters ('~\'!()*')");
     }
 